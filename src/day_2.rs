@@ -1,98 +1,466 @@
 // use lazy_static::lazy_static;
 // use regex::Regex;
+use crate::utils;
 use std::borrow::Borrow;
 use std::cmp;
-use std::fs::File;
-use std::io::{self, BufRead};
-
-pub fn main() {
-    let file = File::open("inputs/input_2.txt").unwrap();
-    let elf_bag = CubeSet {
-        num_red: 12,
-        num_green: 13,
-        num_blue: 14,
+use std::fmt;
+use std::fs;
+use std::io;
+
+const INPUT: &str = "inputs/input_2.txt";
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Number of red cubes in the bag
+    #[arg(long, default_value_t = 12)]
+    red: i32,
+    /// Number of green cubes in the bag
+    #[arg(long, default_value_t = 13)]
+    green: i32,
+    /// Number of blue cubes in the bag
+    #[arg(long, default_value_t = 14)]
+    blue: i32,
+    /// Read the bag from a file instead, e.g. "12 red, 13 green, 14 blue"
+    #[arg(long, conflicts_with_all = ["red", "green", "blue"])]
+    bag: Option<String>,
+    /// List the IDs of the possible games, not just their sum
+    #[arg(long)]
+    list: bool,
+    /// Find the smallest bag that makes exactly these games possible
+    #[arg(long, value_delimiter = ',')]
+    separate: Vec<i32>,
+    /// Estimate each game's bag, and a pooled bag, from the reveals
+    #[arg(long)]
+    estimate: bool,
+    /// Largest number of cubes of any one color considered when estimating
+    #[arg(long, default_value_t = 20)]
+    max_cubes: i32,
+    /// Probability mass covered by each estimated color interval, between 0 and 1
+    #[arg(long, default_value_t = 0.9, value_parser = parse_confidence)]
+    confidence: f64,
+}
+
+// Only a probability strictly between 0 and 1 gives a meaningful interval
+fn parse_confidence(s: &str) -> Result<f64, String> {
+    let confidence: f64 = s.parse().map_err(|_| format!("{s} is not a number"))?;
+    if confidence > 0.0 && confidence < 1.0 {
+        Ok(confidence)
+    } else {
+        Err(format!("{s} is not between 0 and 1"))
+    }
+}
+
+pub fn main(args: &Args) {
+    let elf_bag = if let Some(bag_file) = &args.bag {
+        match read_bag(bag_file) {
+            Ok(bag) => bag,
+            Err(e) => {
+                println!("{bag_file}: {e}");
+                return;
+            }
+        }
+    } else {
+        CubeSet {
+            num_red: args.red,
+            num_green: args.green,
+            num_blue: args.blue,
+        }
+    };
+    println!("Bag: {elf_bag}");
+
+    let games = match games_from_lines(utils::lines_in_file(INPUT)) {
+        Ok(games) => games,
+        Err(e) => {
+            println!("{INPUT}: {e}");
+            return;
+        }
     };
-    let lines = io::BufReader::new(file).lines();
-    let lines = lines.map(|l| l.expect("Bad line!"));
-    let id_sum = id_sum_possible(&elf_bag, lines);
+    if args.list {
+        let ids = possible_games(&elf_bag, &games);
+        println!("Possible games: {ids:?}");
+    }
+    let id_sum = id_sum_possible(&elf_bag, &games);
     println!("id_sum: {id_sum}");
 
-    let file = File::open("inputs/input_2.txt").unwrap();
-    let lines = io::BufReader::new(file).lines();
-    let lines = lines.map(|l| l.expect("Bad line!"));
-    let power_sum = sum_powers(lines);
+    let power_sum = sum_powers(&games);
     println!("power_sum: {power_sum}");
+
+    if !args.separate.is_empty() {
+        match separating_bag(&games, &args.separate) {
+            Ok(bag) => println!(
+                "Smallest bag for games {:?}: {bag} (total {}, power {})",
+                args.separate,
+                bag.get_total(),
+                bag.get_power()
+            ),
+            Err(SeparateError::UnknownGame(id)) => println!("No game with ID {id}"),
+            Err(SeparateError::Inseparable(ids)) => {
+                println!("No bag separates those games; games {ids:?} would also be possible")
+            }
+        }
+    }
+
+    if args.estimate {
+        for game in games.iter() {
+            let estimate =
+                estimate_bag(std::slice::from_ref(game), args.max_cubes, args.confidence);
+            print_estimate(&format!("Game {}", game.id), estimate);
+        }
+        let estimate = estimate_bag(&games, args.max_cubes, args.confidence);
+        print_estimate("All games", estimate);
+    }
+}
+
+fn print_estimate(label: &str, estimate: Option<BagEstimate>) {
+    if let Some(e) = estimate {
+        println!(
+            "{label}: most likely {}; red {}..={}, green {}..={}, blue {}..={}",
+            e.most_likely, e.red.0, e.red.1, e.green.0, e.green.1, e.blue.0, e.blue.1,
+        );
+    } else {
+        println!("{label}: needs more cubes than --max-cubes allows");
+    }
+}
+
+// A bag file holds cube counts written the same way as a reveal. Entries may
+// be separated by commas or newlines, and lines starting with '#' are ignored.
+fn read_bag(filename: &str) -> Result<CubeSet, BagError> {
+    let contents = fs::read_to_string(filename).map_err(BagError::Io)?;
+    let mut bag = CubeSet::empty();
+    for (idx, line) in contents.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        bag.add(&CubeSet::parse(idx + 1, line).map_err(BagError::Parse)?);
+    }
+    Ok(bag)
+}
+
+#[derive(Debug)]
+enum BagError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for BagError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BagError::Io(e) => write!(f, "{e}"),
+            BagError::Parse(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+fn id_sum_possible(elf_bag: &CubeSet, games: &[Game]) -> i32 {
+    possible_games(elf_bag, games).iter().sum()
 }
 
-fn id_sum_possible<I>(elf_bag: &CubeSet, lines: I) -> i32
+fn possible_games(elf_bag: &CubeSet, games: &[Game]) -> Vec<i32> {
+    games
+        .iter()
+        .filter(|g| elf_bag.contains(&g.minimal_bag()))
+        .map(|g| g.id)
+        .collect()
+}
+
+fn sum_powers(games: &[Game]) -> i32 {
+    games.iter().map(|g| g.minimal_bag().get_power()).sum()
+}
+
+// Parses one game per line, skipping blank lines.
+fn games_from_lines<I>(lines: I) -> Result<Vec<Game>, ParseError>
 where
     I: IntoIterator,
     I::Item: Borrow<str>,
 {
-    let mut id_sum: i32 = 0;
-    for line in lines {
-        let g = Game::from_str(line.borrow());
-        if let Some(g) = g {
-            let min_bag = g.minimal_bag();
-            if elf_bag.contains(&min_bag) {
-                id_sum += g.id;
+    let mut games = Vec::new();
+    for (idx, line) in lines.into_iter().enumerate() {
+        let line = line.borrow();
+        if !line.trim().is_empty() {
+            games.push(Game::parse(idx + 1, line)?);
+        }
+    }
+    Ok(games)
+}
+
+#[derive(Debug, PartialEq)]
+enum SeparateError {
+    UnknownGame(i32),
+    // Game IDs outside the target set that any feasible bag would also allow
+    Inseparable(Vec<i32>),
+}
+
+// Finds the smallest bag that makes exactly the target games possible.
+// A bag allows a game iff it contains that game's minimal bag, so the bags
+// allowing every target game are exactly those containing the union of their
+// minimal bags. That union is the element-wise minimum of all such bags, so it
+// minimizes both the total cube count and the power. If it already allows some
+// other game, every larger bag does too, and no bag separates the targets.
+fn separating_bag(games: &[Game], targets: &[i32]) -> Result<CubeSet, SeparateError> {
+    let mut bag = CubeSet::empty();
+    for id in targets {
+        let game = games
+            .iter()
+            .find(|g| g.id == *id)
+            .ok_or(SeparateError::UnknownGame(*id))?;
+        bag.expand_to_hold(&game.minimal_bag());
+    }
+    let extras: Vec<i32> = games
+        .iter()
+        .filter(|g| !targets.contains(&g.id) && bag.contains(&g.minimal_bag()))
+        .map(|g| g.id)
+        .collect();
+    if extras.is_empty() {
+        Ok(bag)
+    } else {
+        Err(SeparateError::Inseparable(extras))
+    }
+}
+
+struct BagEstimate {
+    most_likely: CubeSet,
+    // Central credible intervals (low, high) for each color's count
+    red: (i32, i32),
+    green: (i32, i32),
+    blue: (i32, i32),
+}
+
+/* Each reveal draws n = r + g + b cubes from the bag without replacement, and
+ * the cubes go back in afterwards. For a bag (R, G, B) with N cubes, that's a
+ * multivariate hypergeometric draw:
+ *   P(r, g, b) = C(R, r) C(G, g) C(B, b) / C(N, n)
+ * Multiplying over every reveal gives the likelihood of the bag. With a flat
+ * prior over bags of up to max_cubes of each color, the posterior is the
+ * normalized likelihood; its mode is the most likely bag, and the per-color
+ * marginals give the intervals.
+ */
+fn estimate_bag(games: &[Game], max_cubes: i32, confidence: f64) -> Option<BagEstimate> {
+    let mut minimal = CubeSet::empty();
+    for game in games {
+        minimal.expand_to_hold(&game.minimal_bag());
+    }
+    if minimal.num_red > max_cubes || minimal.num_green > max_cubes || minimal.num_blue > max_cubes
+    {
+        return None;
+    }
+
+    // ln(k!) for k up to the largest possible bag
+    let mut ln_fact = vec![0.0f64; 3 * max_cubes as usize + 1];
+    for k in 1..ln_fact.len() {
+        ln_fact[k] = ln_fact[k - 1] + (k as f64).ln();
+    }
+    let ln_choose =
+        |n: i32, k: i32| ln_fact[n as usize] - ln_fact[k as usize] - ln_fact[(n - k) as usize];
+
+    let mut bags = Vec::new();
+    for red in minimal.num_red..=max_cubes {
+        for green in minimal.num_green..=max_cubes {
+            for blue in minimal.num_blue..=max_cubes {
+                let bag = CubeSet {
+                    num_red: red,
+                    num_green: green,
+                    num_blue: blue,
+                };
+                let mut log_like = 0.0;
+                for reveal in games.iter().flat_map(|g| g.reveals.iter()) {
+                    log_like += ln_choose(red, reveal.num_red)
+                        + ln_choose(green, reveal.num_green)
+                        + ln_choose(blue, reveal.num_blue)
+                        - ln_choose(bag.get_total(), reveal.get_total());
+                }
+                bags.push((bag, log_like));
             }
         }
     }
-    id_sum
+
+    let max_log_like = bags
+        .iter()
+        .map(|(_, l)| *l)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let most_likely = bags.iter().find(|(_, l)| *l == max_log_like)?.0.clone();
+
+    let mut red_marginal = vec![0.0; max_cubes as usize + 1];
+    let mut green_marginal = red_marginal.clone();
+    let mut blue_marginal = red_marginal.clone();
+    let mut total = 0.0;
+    for (bag, log_like) in bags.iter() {
+        let p = (log_like - max_log_like).exp();
+        red_marginal[bag.num_red as usize] += p;
+        green_marginal[bag.num_green as usize] += p;
+        blue_marginal[bag.num_blue as usize] += p;
+        total += p;
+    }
+
+    Some(BagEstimate {
+        most_likely,
+        red: credible_interval(&red_marginal, total, confidence),
+        green: credible_interval(&green_marginal, total, confidence),
+        blue: credible_interval(&blue_marginal, total, confidence),
+    })
 }
 
-fn sum_powers<I>(lines: I) -> i32
-where
-    I: IntoIterator,
-    I::Item: Borrow<str>,
-{
-    let mut power_sum: i32 = 0;
-    for line in lines {
-        let g = Game::from_str(line.borrow());
-        if let Some(g) = g {
-            let min_bag = g.minimal_bag();
-            power_sum += min_bag.get_power();
+// Smallest and largest counts whose cumulative mass reaches the lower and
+// upper tails of a central interval covering `confidence` of the total.
+fn credible_interval(marginal: &[f64], total: f64, confidence: f64) -> (i32, i32) {
+    let tail = (1.0 - confidence) / 2.0 * total;
+    let mut cumulative = 0.0;
+    let mut low = None;
+    let mut high = marginal.len() - 1;
+    for (count, p) in marginal.iter().enumerate() {
+        cumulative += p;
+        if low.is_none() && cumulative > tail {
+            low = Some(count);
+        }
+        if cumulative >= total - tail {
+            high = count;
+            break;
         }
     }
-    power_sum
+    (low.unwrap_or(0) as i32, high as i32)
 }
 
+#[derive(Debug)]
 struct Game {
     id: i32,
     reveals: Vec<CubeSet>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 struct CubeSet {
     num_red: i32,
     num_green: i32,
     num_blue: i32,
 }
 
+#[derive(Debug, PartialEq)]
+struct ParseError {
+    line: usize,
+    column: usize,
+    expected: &'static str,
+    found: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: expected {}, found {}",
+            self.line, self.column, self.expected, self.found
+        )
+    }
+}
+
+// A cursor over one line of text. Whitespace is skipped before every token.
+struct Parser<'a> {
+    line: usize,
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(line: usize, text: &'a str) -> Parser<'a> {
+        Parser { line, text, pos: 0 }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn error(&self, expected: &'static str) -> ParseError {
+        let rest = &self.text[self.pos..];
+        let token_len = rest
+            .find(|c: char| !c.is_alphanumeric())
+            .unwrap_or(rest.len());
+        let found = match rest.chars().next() {
+            None => "end of line".to_owned(),
+            Some(c) if token_len == 0 => format!("{c:?}"),
+            Some(_) => format!("{:?}", &rest[..token_len]),
+        };
+        ParseError {
+            line: self.line,
+            column: self.text[..self.pos].chars().count() + 1,
+            expected,
+            found,
+        }
+    }
+
+    // Consumes `ch` if it's the next token.
+    fn accept(&mut self, ch: char) -> bool {
+        self.skip_whitespace();
+        if self.text[self.pos..].starts_with(ch) {
+            self.pos += ch.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, ch: char, expected: &'static str) -> Result<(), ParseError> {
+        if self.accept(ch) {
+            Ok(())
+        } else {
+            Err(self.error(expected))
+        }
+    }
+
+    fn word(&mut self, expected: &'static str) -> Result<&'a str, ParseError> {
+        self.skip_whitespace();
+        let rest = &self.text[self.pos..];
+        let len = rest
+            .find(|c: char| !c.is_alphabetic())
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error(expected));
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn number(&mut self) -> Result<i32, ParseError> {
+        self.skip_whitespace();
+        let rest = &self.text[self.pos..];
+        let len = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let n = rest[..len].parse().map_err(|_| self.error("a number"))?;
+        self.pos += len;
+        Ok(n)
+    }
+
+    fn end(&mut self, expected: &'static str) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        if self.pos == self.text.len() {
+            Ok(())
+        } else {
+            Err(self.error(expected))
+        }
+    }
+}
+
 impl Game {
-    fn from_str(s: &str) -> Option<Game> {
-        let id_results: Vec<&str> = s.split(":").collect();
-        if id_results.len() != 2 {
-            println!("Bad line: {s:?}");
-            return None;
-        }
-        let mut game_id = id_results[0].trim().split(" ");
-        assert_eq!(game_id.next().unwrap(), "Game");
-        let id = i32::from_str_radix(game_id.next().unwrap(), 10).unwrap();
-        let reveals: Vec<CubeSet> = id_results[1]
-            .split(";")
-            .map(|s| CubeSet::from_str(s))
-            .collect();
-        Some(Game { id, reveals })
+    // <Game> <id>: <reveal>; <reveal>; ...
+    fn parse(line: usize, s: &str) -> Result<Game, ParseError> {
+        let mut p = Parser::new(line, s);
+        let start = p.pos;
+        if p.word("\"Game\"")? != "Game" {
+            p.pos = start;
+            p.skip_whitespace();
+            return Err(p.error("\"Game\""));
+        }
+        let id = p.number()?;
+        p.expect(':', "':'")?;
+        let mut reveals = vec![CubeSet::parse_from(&mut p)?];
+        while p.accept(';') {
+            reveals.push(CubeSet::parse_from(&mut p)?);
+        }
+        p.end("',', ';' or end of line")?;
+        Ok(Game { id, reveals })
     }
 
     fn minimal_bag(&self) -> CubeSet {
-        let mut minimal = CubeSet {
-            num_red: 0,
-            num_green: 0,
-            num_blue: 0,
-        };
+        let mut minimal = CubeSet::empty();
         for reveal in self.reveals.iter() {
             minimal.expand_to_hold(reveal);
         }
@@ -100,25 +468,72 @@ impl Game {
     }
 }
 
+// Canonical form: "Game <id>: " followed by the reveals, separated by "; ".
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Game {}: ", self.id)?;
+        for (idx, reveal) in self.reveals.iter().enumerate() {
+            if idx > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{reveal}")?;
+        }
+        Ok(())
+    }
+}
+
 impl CubeSet {
-    fn from_str(s: &str) -> CubeSet {
-        let mut cs = CubeSet {
+    fn empty() -> CubeSet {
+        CubeSet {
             num_red: 0,
             num_green: 0,
             num_blue: 0,
-        };
-        for cubes in s.split(",") {
-            let count_color: Vec<&str> = cubes.trim().split(" ").collect();
-            assert_eq!(count_color.len(), 2);
-            let count = i32::from_str_radix(count_color[0], 10).unwrap();
-            match count_color[1] {
-                "red" => cs.num_red += count,
-                "green" => cs.num_green += count,
-                "blue" => cs.num_blue += count,
-                _ => panic!("Unknown color {}", count_color[1]),
+        }
+    }
+
+    fn parse(line: usize, s: &str) -> Result<CubeSet, ParseError> {
+        let mut p = Parser::new(line, s);
+        let cs = CubeSet::parse_from(&mut p)?;
+        p.end("',' or end of line")?;
+        Ok(cs)
+    }
+
+    // <count> <color>, <count> <color>, ...
+    // A color may appear more than once; its counts are added together.
+    fn parse_from(p: &mut Parser) -> Result<CubeSet, ParseError> {
+        let mut cs = CubeSet::empty();
+        loop {
+            p.skip_whitespace();
+            let count_start = p.pos;
+            let count = p.number()?;
+            let start = p.pos;
+            let total = match p.word("a color")? {
+                "red" => &mut cs.num_red,
+                "green" => &mut cs.num_green,
+                "blue" => &mut cs.num_blue,
+                _ => {
+                    p.pos = start;
+                    p.skip_whitespace();
+                    return Err(p.error("red, green or blue"));
+                }
+            };
+            *total = match total.checked_add(count) {
+                Some(sum) => sum,
+                None => {
+                    p.pos = count_start;
+                    return Err(p.error("a count that keeps the color's total in range"));
+                }
+            };
+            if !p.accept(',') {
+                return Ok(cs);
             }
         }
-        cs
+    }
+
+    fn add(&mut self, other: &CubeSet) {
+        self.num_red += other.num_red;
+        self.num_green += other.num_green;
+        self.num_blue += other.num_blue;
     }
 
     // self.expand_to_hold(other) -> self = union(self, other)
@@ -137,10 +552,41 @@ impl CubeSet {
     fn get_power(&self) -> i32 {
         self.num_red * self.num_green * self.num_blue
     }
+
+    fn get_total(&self) -> i32 {
+        self.num_red + self.num_green + self.num_blue
+    }
+}
+
+// Canonical form lists the colors present in red, green, blue order. An empty
+// set is written out in full so that it still parses.
+impl fmt::Display for CubeSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counts = [
+            (self.num_red, "red"),
+            (self.num_green, "green"),
+            (self.num_blue, "blue"),
+        ];
+        let all = *self == CubeSet::empty();
+        let mut first = true;
+        for (count, color) in counts {
+            if count > 0 || all {
+                if !first {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{count} {color}")?;
+                first = false;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
     use std::iter::zip;
 
     use super::*;
@@ -152,22 +598,52 @@ mod tests {
     Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green
     "#;
 
+    fn bag(s: &str) -> CubeSet {
+        CubeSet::parse(1, s).unwrap()
+    }
+
+    fn example_games() -> Vec<Game> {
+        games_from_lines(EXAMPLE_1.lines()).unwrap()
+    }
+
+    #[test]
+    fn test_read_bag() {
+        let filename = std::env::temp_dir().join("aoc2023_day_2_bag.txt");
+        let filename = filename.to_str().unwrap();
+        fs::write(filename, "# Elf's bag\n12 red, 13 green\n14 blue\n").unwrap();
+        assert_eq!(
+            read_bag(filename).unwrap(),
+            bag("12 red, 13 green, 14 blue")
+        );
+        fs::write(filename, "12 red, 13 purple\n").unwrap();
+        assert!(matches!(read_bag(filename), Err(BagError::Parse(_))));
+        fs::remove_file(filename).unwrap();
+        assert!(matches!(read_bag(filename), Err(BagError::Io(_))));
+    }
+
+    #[test]
+    fn test_parse_confidence() {
+        assert_eq!(parse_confidence("0.5"), Ok(0.5));
+        assert!(parse_confidence("0").is_err());
+        assert!(parse_confidence("1").is_err());
+        assert!(parse_confidence("-0.2").is_err());
+        assert!(parse_confidence("1.5").is_err());
+        assert!(parse_confidence("NaN").is_err());
+        assert!(parse_confidence("most").is_err());
+    }
+
     #[test]
     fn test_game_from_str() {
-        let results = vec![true, true, false, false, true];
+        let results = [true, true, false, false, true];
         let elf_bag = CubeSet {
             num_red: 12,
             num_green: 13,
             num_blue: 14,
         };
-        for (line, expected_result) in zip(EXAMPLE_1.lines(), results.iter()) {
-            let g = Game::from_str(line);
-            if let Some(g) = g {
-                let min_bag = g.minimal_bag();
-                assert_eq!(elf_bag.contains(&min_bag), *expected_result);
-            } else {
-                assert!(false);
-            }
+        for (idx, (line, expected_result)) in zip(EXAMPLE_1.lines(), results.iter()).enumerate() {
+            let g = Game::parse(idx + 1, line).unwrap();
+            let min_bag = g.minimal_bag();
+            assert_eq!(elf_bag.contains(&min_bag), *expected_result);
         }
     }
 
@@ -178,22 +654,193 @@ mod tests {
             num_green: 13,
             num_blue: 14,
         };
-        let id_sum = id_sum_possible(&elf_bag, EXAMPLE_1.lines());
+        let id_sum = id_sum_possible(&elf_bag, &example_games());
         println!("test ID sum = {}", id_sum);
         assert_eq!(id_sum, 8);
     }
 
+    #[test]
+    fn test_possible_games() {
+        let games = example_games();
+        let elf_bag = bag("12 red, 13 green, 14 blue");
+        assert_eq!(possible_games(&elf_bag, &games), vec![1, 2, 5]);
+        let small_bag = bag("4 red, 3 green, 6 blue");
+        assert_eq!(possible_games(&small_bag, &games), vec![1, 2]);
+    }
+
     #[test]
     fn day_2() {
-        let results = vec![48, 12, 1560, 630, 36];
-        for (line, expected_result) in zip(EXAMPLE_1.lines(), results.iter()) {
-            let g = Game::from_str(line);
-            if let Some(g) = g {
-                let min_bag = g.minimal_bag();
-                assert_eq!(min_bag.get_power(), *expected_result);
+        let results = [48, 12, 1560, 630, 36];
+        let games = example_games();
+        for (g, expected_result) in zip(games.iter(), results.iter()) {
+            let min_bag = g.minimal_bag();
+            assert_eq!(min_bag.get_power(), *expected_result);
+        }
+
+        assert_eq!(sum_powers(&games), 2286);
+    }
+
+    #[test]
+    fn test_separating_bag() {
+        let games = example_games();
+        assert_eq!(
+            separating_bag(&games, &[1, 2]),
+            Ok(bag("4 red, 3 green, 6 blue"))
+        );
+        assert_eq!(
+            separating_bag(&games, &[1, 2, 5]),
+            Ok(bag("6 red, 3 green, 6 blue"))
+        );
+        assert_eq!(
+            separating_bag(&games, &[1]),
+            Ok(bag("4 red, 2 green, 6 blue"))
+        );
+        // Game 3 needs so many cubes that games 1, 2 and 5 come along with it
+        assert_eq!(
+            separating_bag(&games, &[3]),
+            Err(SeparateError::Inseparable(vec![1, 2, 5]))
+        );
+        assert_eq!(
+            separating_bag(&games, &[7]),
+            Err(SeparateError::UnknownGame(7))
+        );
+    }
+
+    #[test]
+    fn test_estimate_bag() {
+        // Drawing all six cubes twenty times over makes any larger bag unlikely
+        let line = format!("Game 1: {}", vec!["2 red, 2 green, 2 blue"; 20].join("; "));
+        let games = games_from_lines([line]).unwrap();
+        let e = estimate_bag(&games, 10, 0.9).unwrap();
+        assert_eq!(e.most_likely, bag("2 red, 2 green, 2 blue"));
+        assert_eq!((e.red, e.green, e.blue), ((2, 2), (2, 2), (2, 2)));
+
+        let games = example_games();
+        let e = estimate_bag(&games, 25, 0.9).unwrap();
+        assert!(e.most_likely.contains(&bag("20 red, 13 green, 15 blue")));
+        assert!(e.red.0 <= e.most_likely.num_red && e.most_likely.num_red <= e.red.1);
+        assert!(estimate_bag(&games, 19, 0.9).is_none());
+    }
+
+    #[test]
+    fn test_parse_tolerant() {
+        let g = Game::parse(1, "  Game   7 :3 blue ,4  red;1 red, 2 red ,2 green  ").unwrap();
+        assert_eq!(g.id, 7);
+        assert_eq!(g.reveals, vec![bag("4 red, 3 blue"), bag("3 red, 2 green")]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |s| Game::parse(4, s).unwrap_err();
+        assert_eq!(
+            err("Gane 1: 3 blue"),
+            ParseError {
+                line: 4,
+                column: 1,
+                expected: "\"Game\"",
+                found: "\"Gane\"".to_owned(),
             }
+        );
+        let e = err("Game x: 3 blue");
+        assert_eq!((e.column, e.expected), (6, "a number"));
+        let e = err("Game 1 3 blue");
+        assert_eq!((e.column, e.expected), (8, "':'"));
+        let e = err("Game 1: 3 blue; 4 purple");
+        assert_eq!((e.column, e.expected), (19, "red, green or blue"));
+        assert_eq!(e.found, "\"purple\"");
+        let e = err("Game 1: 3 blue 4 red");
+        assert_eq!((e.column, e.expected), (16, "',', ';' or end of line"));
+        let e = err("Game 1: 3 blue,");
+        assert_eq!((e.column, e.found.as_str()), (16, "end of line"));
+        assert_eq!(
+            e.to_string(),
+            "line 4, column 16: expected a number, found end of line"
+        );
+        let e = err("Game 1: 2000000000 red, 2000000000 red");
+        assert_eq!((e.column, e.found.as_str()), (25, "\"2000000000\""));
+        assert!(games_from_lines(["Game 1: 1 red", "", "Game 2: red"]).is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for g in example_games() {
+            let text = g.to_string();
+            let g2 = Game::parse(1, &text).unwrap();
+            assert_eq!(g2.id, g.id);
+            assert_eq!(g2.reveals, g.reveals);
+            assert_eq!(g2.to_string(), text);
         }
+        assert_eq!(
+            example_games()[0].to_string(),
+            "Game 1: 4 red, 3 blue; 1 red, 2 green, 6 blue; 2 green"
+        );
+        assert_eq!(CubeSet::empty().to_string(), "0 red, 0 green, 0 blue");
+        assert_eq!(bag(&CubeSet::empty().to_string()), CubeSet::empty());
+    }
 
-        assert_eq!(sum_powers(EXAMPLE_1.lines()), 2286);
+    fn random_bag(rng: &mut StdRng) -> CubeSet {
+        CubeSet {
+            num_red: rng.gen_range(0..=20),
+            num_green: rng.gen_range(0..=20),
+            num_blue: rng.gen_range(0..=20),
+        }
+    }
+
+    // Writes a game line whose reveals are drawn from a hidden bag without
+    // replacement, listing the colors drawn in a random order.
+    fn random_game(rng: &mut StdRng, id: i32, hidden: &CubeSet) -> String {
+        let mut cubes = Vec::new();
+        cubes.extend(std::iter::repeat_n("red", hidden.num_red as usize));
+        cubes.extend(std::iter::repeat_n("green", hidden.num_green as usize));
+        cubes.extend(std::iter::repeat_n("blue", hidden.num_blue as usize));
+        let mut reveals = Vec::new();
+        for _ in 0..rng.gen_range(1..=6) {
+            cubes.shuffle(rng);
+            let drawn = &cubes[..rng.gen_range(1..=cubes.len())];
+            let mut colors = ["red", "green", "blue"];
+            colors.shuffle(rng);
+            let counts: Vec<String> = colors
+                .iter()
+                .map(|color| (drawn.iter().filter(|c| *c == color).count(), color))
+                .filter(|(count, _)| *count > 0)
+                .map(|(count, color)| format!("{count} {color}"))
+                .collect();
+            reveals.push(counts.join(", "));
+        }
+        format!("Game {id}: {}", reveals.join("; "))
+    }
+
+    #[test]
+    fn test_random_games() {
+        let mut rng = StdRng::seed_from_u64(2023);
+        for id in 1..=500 {
+            let mut hidden = random_bag(&mut rng);
+            if hidden == CubeSet::empty() {
+                hidden.num_red = 1;
+            }
+            let line = random_game(&mut rng, id, &hidden);
+            let g = Game::parse(1, &line).unwrap();
+            assert_eq!(g.id, id);
+            let min_bag = g.minimal_bag();
+            for reveal in g.reveals.iter() {
+                assert!(min_bag.contains(reveal), "{line}");
+            }
+            assert!(hidden.contains(&min_bag), "{line}");
+            assert_eq!(possible_games(&hidden, &[g]), vec![id]);
+        }
+    }
+
+    #[test]
+    fn test_expand_power_monotone() {
+        let mut rng = StdRng::seed_from_u64(6);
+        for _ in 0..1000 {
+            let a = random_bag(&mut rng);
+            let b = random_bag(&mut rng);
+            let mut union = a.clone();
+            union.expand_to_hold(&b);
+            assert!(union.contains(&a) && union.contains(&b));
+            assert!(union.get_power() >= a.get_power());
+            assert!(union.get_power() >= b.get_power());
+        }
     }
 }
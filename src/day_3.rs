@@ -1,107 +1,351 @@
-// use std::borrow::Borrow;
+use std::borrow::Borrow;
 // use std::cmp;
-use lazy_static::lazy_static;
-use regex::Regex;
-use std::fs::File;
+use std::fmt;
+use std::fmt::Write;
+use std::fs::{self, File};
 use std::io::{self, BufRead};
+use std::ops::RangeInclusive;
 
 const INPUT: &str = "inputs/input_3.txt";
 
-pub fn main() {
+#[derive(clap::Args)]
+pub struct Args {
+    /// How to total numbers that touch more than one symbol
+    #[arg(long, value_enum, default_value_t = PartCounting::PerNumber)]
+    counting: PartCounting,
+    /// Symbols that can be gears
+    #[arg(long, default_value = "*")]
+    gear_symbols: String,
+    /// How many neighboring numbers make a gear, e.g. "2" or "1..=3"
+    #[arg(long, default_value = "2", value_parser = parse_neighbor_range)]
+    gear_neighbors: RangeInclusive<usize>,
+    /// How to combine a gear's neighboring numbers into its ratio
+    #[arg(long, value_enum, default_value_t = GearRatio::Product)]
+    gear_ratio: GearRatio,
+    /// List every gear found, not just the total of their ratios
+    #[arg(long, conflicts_with = "stream")]
+    list_gears: bool,
+    /// Solve while reading, keeping only three rows in memory at a time
+    #[arg(long)]
+    stream: bool,
+    /// Print the schematic with part numbers and gears highlighted
+    #[arg(long, conflicts_with = "stream")]
+    render: bool,
+    /// Write the highlighted schematic to an HTML file
+    #[arg(long, conflicts_with = "stream")]
+    html: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, clap::ValueEnum)]
+enum PartCounting {
+    /// Count each part number once, however many symbols it touches
+    PerNumber,
+    /// Count a number once for every symbol it touches
+    PerSymbol,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, clap::ValueEnum)]
+enum GearRatio {
+    /// Multiply the neighboring numbers
+    Product,
+    /// Add the neighboring numbers
+    Sum,
+}
+
+fn parse_neighbor_range(s: &str) -> Result<RangeInclusive<usize>, String> {
+    let bad = |_| format!("expected a count or a range like 1..=3, got {s:?}");
+    if let Some((low, high)) = s.split_once("..=") {
+        Ok(low.parse().map_err(bad)?..=high.parse().map_err(bad)?)
+    } else {
+        let n = s.parse().map_err(bad)?;
+        Ok(n..=n)
+    }
+}
+
+pub fn main(args: &Args) {
+    let rule = GearRule {
+        symbols: args.gear_symbols.chars().collect(),
+        neighbors: args.gear_neighbors.clone(),
+        ratio: match args.gear_ratio {
+            GearRatio::Product => product,
+            GearRatio::Sum => sum,
+        },
+    };
+
     let file = File::open(INPUT).unwrap();
-    let mut lines = Vec::new();
-    for (row, line) in io::BufReader::new(file).lines().enumerate() {
-        let line = line.unwrap();
-        let parsed = Line::from_str(row, &line);
-        if let Some(line) = parsed {
-            lines.push(line);
-        } else {
-            println!("Unparsed line: {line:?}");
+    let rows = io::BufReader::new(file).lines().map(|l| l.unwrap());
+    if args.stream {
+        match stream_totals(rows, args.counting, &rule) {
+            Ok((total, total_2)) => {
+                println!("Pt 1: Total of part numbers: {total}");
+                println!("Pt 2: Total of gear ratios: {total_2}");
+            }
+            Err(e) => println!("{INPUT}: {e}"),
         }
+        return;
     }
+    let lines = match parse_schematic(rows) {
+        Ok(lines) => lines,
+        Err(e) => {
+            println!("{INPUT}: {e}");
+            return;
+        }
+    };
 
-    let total = total_part_numbers(&lines);
+    if args.render {
+        print!("{}", render_ansi(&lines, &rule));
+    }
+    if let Some(html_file) = &args.html {
+        match fs::write(html_file, render_html(&lines, &rule)) {
+            Ok(()) => println!("Wrote {html_file}"),
+            Err(e) => println!("{html_file}: {e}"),
+        }
+    }
+
+    let total = total_part_numbers(&lines, args.counting);
     println!("Pt 1: Total of part numbers: {total}");
 
-    let total_2 = total_gear_ratios(&lines);
+    if args.list_gears {
+        for gear in find_gears(&lines, &rule) {
+            println!(
+                "Gear {:?} at row {}, column {}: {:?} -> {}",
+                gear.symbol, gear.row, gear.column, gear.neighbors, gear.ratio
+            );
+        }
+    }
+    let total_2 = total_gear_ratios(&lines, &rule);
     println!("Pt 2: Total of gear ratios: {total_2}");
 }
 
-fn total_part_numbers(lines: &Vec<Line>) -> usize {
-    let mut total = 0;
+#[derive(Debug, PartialEq)]
+enum SchematicError {
+    RaggedRow {
+        row: usize,
+        width: usize,
+        expected: usize,
+    },
+    NumberTooLarge {
+        row: usize,
+        column: usize,
+    },
+}
+
+impl fmt::Display for SchematicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchematicError::RaggedRow {
+                row,
+                width,
+                expected,
+            } => write!(
+                f,
+                "line {} is {width} columns wide, expected {expected} like the first line",
+                row + 1
+            ),
+            SchematicError::NumberTooLarge { row, column } => write!(
+                f,
+                "number at line {}, column {} is too large",
+                row + 1,
+                column + 1
+            ),
+        }
+    }
+}
+
+// Parses every row of the schematic, keeping row and column positions exactly
+// as they are in the input. All rows must be the same width.
+fn parse_schematic<I>(rows: I) -> Result<Vec<Line>, SchematicError>
+where
+    I: IntoIterator,
+    I::Item: Borrow<str>,
+{
+    parse_rows(rows).collect()
+}
+
+// Parses rows one at a time as they're read.
+fn parse_rows<I>(rows: I) -> impl Iterator<Item = Result<Line, SchematicError>>
+where
+    I: IntoIterator,
+    I::Item: Borrow<str>,
+{
+    let mut expected = None;
+    rows.into_iter().enumerate().map(move |(row, s)| {
+        let s = s.borrow();
+        let width = s.chars().count();
+        let expected = *expected.get_or_insert(width);
+        if width != expected {
+            return Err(SchematicError::RaggedRow {
+                row,
+                width,
+                expected,
+            });
+        }
+        Line::from_str(row, s)
+    })
+}
+
+// Totals part numbers and gear ratios while reading, holding only the rows
+// above and below the one being evaluated. Gives the same results as
+// total_part_numbers and total_gear_ratios on the whole schematic.
+fn stream_totals<I>(
+    rows: I,
+    counting: PartCounting,
+    rule: &GearRule,
+) -> Result<(usize, usize), SchematicError>
+where
+    I: IntoIterator,
+    I::Item: Borrow<str>,
+{
     let empty = Line::empty();
-    for sym_idx in 0..lines.len() {
-        let prev = if sym_idx > 0 {
-            &lines[sym_idx - 1]
-        } else {
-            &empty
-        };
-        let cur = &lines[sym_idx];
-        let next = if sym_idx < lines.len() - 1 {
-            &lines[sym_idx + 1]
-        } else {
-            &empty
-        };
-        for num in cur.numbers.iter() {
-            for sym in prev
-                .symbols
-                .iter()
-                .chain(cur.symbols.iter())
-                .chain(next.symbols.iter())
-            {
-                if sym.column + 1 >= num.first_col && sym.column <= num.last_col + 1 {
-                    total += num.number;
-                }
-            }
+    let mut part_total = 0;
+    let mut gear_total = 0;
+    let mut evaluate = |window: [&Line; 3]| {
+        part_total += window_part_numbers(window, counting);
+        gear_total += window_gears(window, rule)
+            .iter()
+            .map(|g| g.ratio)
+            .sum::<usize>();
+    };
+
+    let mut prev: Option<Line> = None;
+    let mut cur: Option<Line> = None;
+    for next in parse_rows(rows) {
+        let next = next?;
+        if let Some(cur) = &cur {
+            evaluate([prev.as_ref().unwrap_or(&empty), cur, &next]);
         }
+        prev = cur.replace(next);
     }
-    total
+    if let Some(cur) = &cur {
+        evaluate([prev.as_ref().unwrap_or(&empty), cur, &empty]);
+    }
+    Ok((part_total, gear_total))
 }
 
-fn total_gear_ratios(lines: &Vec<Line>) -> usize {
-    let mut total = 0;
+// A part number is any number adjacent to a symbol, including diagonally.
+fn total_part_numbers(lines: &[Line], counting: PartCounting) -> usize {
     let empty = Line::empty();
-    for sym_idx in 0..lines.len() {
-        let prev = if sym_idx > 0 {
-            &lines[sym_idx - 1]
-        } else {
-            &empty
-        };
-        let cur = &lines[sym_idx];
-        let next = if sym_idx < lines.len() - 1 {
-            &lines[sym_idx + 1]
-        } else {
-            &empty
+    (0..lines.len())
+        .map(|idx| window_part_numbers(window(lines, idx, &empty), counting))
+        .sum()
+}
+
+// The rows above, at and below `idx`, using a blank row past either edge.
+fn window<'a>(lines: &'a [Line], idx: usize, empty: &'a Line) -> [&'a Line; 3] {
+    [
+        if idx > 0 { &lines[idx - 1] } else { empty },
+        &lines[idx],
+        lines.get(idx + 1).unwrap_or(empty),
+    ]
+}
+
+// Totals the part numbers in the middle row of a window, looking up only the
+// cells around each number.
+fn window_part_numbers(window: [&Line; 3], counting: PartCounting) -> usize {
+    let mut total = 0;
+    for num in window[1].numbers.iter() {
+        let touching = symbols_touching(window, num);
+        total += match counting {
+            PartCounting::PerNumber if touching > 0 => num.number,
+            PartCounting::PerNumber => 0,
+            PartCounting::PerSymbol => num.number * touching,
         };
-        let mut number_neighbors = Vec::new();
-        for sym in cur.symbols.iter() {
-            if sym.symbol != '*' {
-                continue;
-            }
-            number_neighbors.clear();
-            for num in prev
-                .numbers
-                .iter()
-                .chain(cur.numbers.iter())
-                .chain(next.numbers.iter())
-            {
-                if sym.column + 1 >= num.first_col && sym.column <= num.last_col + 1 {
-                    number_neighbors.push(num.number);
+    }
+    total
+}
+
+// How many symbols surround a number in the middle row of a window.
+fn symbols_touching(window: [&Line; 3], num: &Number) -> usize {
+    let columns = num.first_col.saturating_sub(1)..=num.last_col + 1;
+    window
+        .iter()
+        .flat_map(|line| columns.clone().map(|column| line.cell(column)))
+        .filter(|cell| *cell == Cell::Symbol)
+        .count()
+}
+
+// Decides which symbols are gears and what their ratios are.
+struct GearRule {
+    symbols: Vec<char>,
+    neighbors: RangeInclusive<usize>,
+    ratio: fn(&[usize]) -> usize,
+}
+
+fn product(nums: &[usize]) -> usize {
+    nums.iter().product()
+}
+
+fn sum(nums: &[usize]) -> usize {
+    nums.iter().sum()
+}
+
+struct Gear {
+    row: usize,
+    column: usize,
+    symbol: char,
+    neighbors: Vec<usize>,
+    ratio: usize,
+}
+
+fn find_gears(lines: &[Line], rule: &GearRule) -> Vec<Gear> {
+    let empty = Line::empty();
+    (0..lines.len())
+        .flat_map(|idx| window_gears(window(lines, idx, &empty), rule))
+        .collect()
+}
+
+// Finds the gears among the symbols in the middle row of a window.
+fn window_gears(window: [&Line; 3], rule: &GearRule) -> Vec<Gear> {
+    let mut gears = Vec::new();
+    for sym in window[1].symbols.iter() {
+        if !rule.symbols.contains(&sym.symbol) {
+            continue;
+        }
+        let mut neighbors = Vec::new();
+        for line in window {
+            // A number spanning several of these cells is only counted once.
+            let mut last = None;
+            for column in sym.column.saturating_sub(1)..=sym.column + 1 {
+                if let Cell::Number(idx) = line.cell(column) {
+                    if last != Some(idx) {
+                        neighbors.push(line.numbers[idx as usize].number);
+                    }
+                    last = Some(idx);
                 }
             }
-            if number_neighbors.len() == 2 {
-                total += number_neighbors[0] * number_neighbors[1];
-            }
+        }
+        if rule.neighbors.contains(&neighbors.len()) {
+            gears.push(Gear {
+                row: window[1].row,
+                column: sym.column,
+                symbol: sym.symbol,
+                ratio: (rule.ratio)(&neighbors),
+                neighbors,
+            });
         }
     }
-    total
+    gears
+}
+
+fn total_gear_ratios(lines: &[Line], rule: &GearRule) -> usize {
+    find_gears(lines, rule).iter().map(|g| g.ratio).sum()
 }
 
-#[allow(dead_code)]
 struct Line {
     row: usize,
+    text: String,
     symbols: Vec<Symbol>,
     numbers: Vec<Number>,
+    // What's in each column, so neighbors can be looked up directly
+    cells: Vec<Cell>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Cell {
+    Blank,
+    Symbol,
+    // Index into the row's numbers
+    Number(u32),
 }
 
 #[allow(dead_code)]
@@ -121,27 +365,37 @@ impl Line {
     fn empty() -> Line {
         Line {
             row: 0,
+            text: String::new(),
             symbols: Vec::new(),
             numbers: Vec::new(),
+            cells: Vec::new(),
         }
     }
 
-    fn from_str(row: usize, s: &str) -> Option<Line> {
-        lazy_static! {
-            static ref OK_LINE_RE: Regex = Regex::new(r"\.").unwrap();
+    fn from_str(row: usize, s: &str) -> Result<Line, SchematicError> {
+        let symbols = Symbol::from_line(s);
+        let numbers = Number::from_line(s)
+            .map_err(|column| SchematicError::NumberTooLarge { row, column })?;
+        let mut cells = vec![Cell::Blank; s.chars().count()];
+        for sym in symbols.iter() {
+            cells[sym.column] = Cell::Symbol;
         }
-        if !OK_LINE_RE.is_match(s) {
-            return None;
+        for (idx, num) in numbers.iter().enumerate() {
+            cells[num.first_col..=num.last_col].fill(Cell::Number(idx as u32));
         }
-        let s = s.trim();
-        let symbols = Symbol::from_line(s);
-        let numbers = Number::from_line(s);
-        Some(Line {
+        Ok(Line {
             row,
+            text: s.to_owned(),
             symbols,
             numbers,
+            cells,
         })
     }
+
+    // Columns past either edge of the row are blank.
+    fn cell(&self, column: usize) -> Cell {
+        self.cells.get(column).copied().unwrap_or(Cell::Blank)
+    }
 }
 
 impl Symbol {
@@ -150,7 +404,7 @@ impl Symbol {
         for (column, ch) in line.chars().enumerate() {
             let sym = match ch {
                 '.' => None,
-                _ if ch.is_alphanumeric() => None,
+                _ if ch.is_alphanumeric() || ch.is_whitespace() => None,
                 _ => Some(ch),
             };
             if let Some(symbol) = sym {
@@ -162,26 +416,184 @@ impl Symbol {
 }
 
 impl Number {
-    fn from_line(line: &str) -> Vec<Number> {
-        lazy_static! {
-            static ref NUM_RE: Regex = Regex::new(r"\d+").unwrap();
+    // On overflow, returns the column where the offending number starts.
+    fn from_line(line: &str) -> Result<Vec<Number>, usize> {
+        let mut numbers = Vec::new();
+        let mut current: Option<Number> = None;
+        for (column, ch) in line.chars().enumerate() {
+            if let Some(digit) = ch.to_digit(10) {
+                let num = current.get_or_insert(Number {
+                    number: 0,
+                    first_col: column,
+                    last_col: column,
+                });
+                num.number = num
+                    .number
+                    .checked_mul(10)
+                    .and_then(|n| n.checked_add(digit as usize))
+                    .ok_or(num.first_col)?;
+                num.last_col = column;
+            } else if let Some(num) = current.take() {
+                numbers.push(num);
+            }
         }
+        numbers.extend(current);
+        Ok(numbers)
+    }
+}
 
-        let mut numbers = Vec::new();
-        for m in NUM_RE.find_iter(line) {
-            numbers.push(Number {
-                number: usize::from_str_radix(m.as_str(), 10).unwrap(),
-                first_col: m.start(),
-                last_col: m.end() - 1,
-            });
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Highlight {
+    Plain,
+    Symbol,
+    Gear,
+    // A number touching exactly one symbol
+    Part,
+    // A number touching several symbols, which are easy to count twice
+    MultiPart,
+    NotPart,
+}
+
+impl Highlight {
+    fn ansi_code(&self) -> &'static str {
+        match self {
+            Highlight::Plain => "",
+            Highlight::Symbol => "36",
+            Highlight::Gear => "1;33",
+            Highlight::Part => "32",
+            Highlight::MultiPart => "1;35",
+            Highlight::NotPart => "31",
+        }
+    }
+
+    fn css_class(&self) -> &'static str {
+        match self {
+            Highlight::Plain => "",
+            Highlight::Symbol => "symbol",
+            Highlight::Gear => "gear",
+            Highlight::Part => "part",
+            Highlight::MultiPart => "multi-part",
+            Highlight::NotPart => "not-part",
+        }
+    }
+}
+
+// How to highlight each column of the middle row of a window.
+fn row_highlights(window: [&Line; 3], rule: &GearRule) -> Vec<Highlight> {
+    let line = window[1];
+    let mut highlights = vec![Highlight::Plain; line.cells.len()];
+    for sym in line.symbols.iter() {
+        highlights[sym.column] = Highlight::Symbol;
+    }
+    for gear in window_gears(window, rule) {
+        highlights[gear.column] = Highlight::Gear;
+    }
+    for num in line.numbers.iter() {
+        let highlight = match symbols_touching(window, num) {
+            0 => Highlight::NotPart,
+            1 => Highlight::Part,
+            _ => Highlight::MultiPart,
+        };
+        highlights[num.first_col..=num.last_col].fill(highlight);
+    }
+    highlights
+}
+
+// Splits each row into runs of characters sharing a highlight.
+fn highlighted_runs(lines: &[Line], rule: &GearRule) -> Vec<Vec<(Highlight, String)>> {
+    let empty = Line::empty();
+    let mut rows = Vec::new();
+    for idx in 0..lines.len() {
+        let highlights = row_highlights(window(lines, idx, &empty), rule);
+        let mut runs: Vec<(Highlight, String)> = Vec::new();
+        for (ch, highlight) in lines[idx].text.chars().zip(highlights) {
+            match runs.last_mut() {
+                Some((last, text)) if *last == highlight => text.push(ch),
+                _ => runs.push((highlight, ch.to_string())),
+            }
+        }
+        rows.push(runs);
+    }
+    rows
+}
+
+fn render_ansi(lines: &[Line], rule: &GearRule) -> String {
+    let mut out = String::new();
+    for runs in highlighted_runs(lines, rule) {
+        for (highlight, text) in runs {
+            if highlight == Highlight::Plain {
+                out.push_str(&text);
+            } else {
+                write!(out, "\x1b[{}m{text}\x1b[0m", highlight.ansi_code()).unwrap();
+            }
+        }
+        out.push('\n');
+    }
+    let legend = [
+        (Highlight::Part, "part number"),
+        (Highlight::MultiPart, "touches several symbols"),
+        (Highlight::NotPart, "not a part number"),
+        (Highlight::Gear, "gear"),
+        (Highlight::Symbol, "symbol"),
+    ];
+    for (highlight, label) in legend {
+        write!(out, "\x1b[{}m{label}\x1b[0m  ", highlight.ansi_code()).unwrap();
+    }
+    out.push('\n');
+    out
+}
+
+fn render_html(lines: &[Line], rule: &GearRule) -> String {
+    let mut out = String::from(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Day 3 schematic</title>
+<style>
+.symbol { color: teal; }
+.gear { color: darkorange; font-weight: bold; }
+.part { color: green; }
+.multi-part { color: magenta; font-weight: bold; }
+.not-part { color: red; }
+</style>
+</head>
+<body>
+<p><span class="part">part number</span> <span class="multi-part">touches several symbols</span>
+<span class="not-part">not a part number</span> <span class="gear">gear</span>
+<span class="symbol">symbol</span></p>
+<pre>
+"#,
+    );
+    for runs in highlighted_runs(lines, rule) {
+        for (highlight, text) in runs {
+            let text = text
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;");
+            if highlight == Highlight::Plain {
+                out.push_str(&text);
+            } else {
+                write!(
+                    out,
+                    "<span class=\"{}\">{text}</span>",
+                    highlight.css_class()
+                )
+                .unwrap();
+            }
         }
-        numbers
+        out.push('\n');
     }
+    out.push_str("</pre>\n</body>\n</html>\n");
+    out
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::time::Instant;
     const EXAMPLE_1: &str = r#"467..114..
     ...*......
     ..35..633.
@@ -193,13 +605,17 @@ mod tests {
     ...$.*....
     .664.598.."#;
 
+    // The puzzle's rule: a '*' next to exactly two numbers, multiplied together
+    fn puzzle_rule() -> GearRule {
+        GearRule {
+            symbols: vec!['*'],
+            neighbors: 2..=2,
+            ratio: product,
+        }
+    }
+
     fn sample_lines() -> Vec<Line> {
-        EXAMPLE_1
-            .lines()
-            .enumerate()
-            .map(|(i, s)| Line::from_str(i, s))
-            .filter_map(std::convert::identity)
-            .collect()
+        parse_schematic(EXAMPLE_1.lines().map(|l| l.trim())).unwrap()
     }
 
     #[test]
@@ -214,14 +630,294 @@ mod tests {
     #[test]
     fn test_pt_1() {
         let lines = sample_lines();
-        let total = total_part_numbers(&lines);
+        let total = total_part_numbers(&lines, PartCounting::PerNumber);
+        assert_eq!(total, 4361);
+        let total = total_part_numbers(&lines, PartCounting::PerSymbol);
         assert_eq!(total, 4361);
     }
 
+    fn parse_lines(s: &str) -> Vec<Line> {
+        parse_schematic(s.lines()).unwrap()
+    }
+
+    #[test]
+    fn test_multiple_symbols() {
+        // Symbols on both sides in the same row
+        let lines = parse_lines("*12#.\n.....");
+        assert_eq!(total_part_numbers(&lines, PartCounting::PerNumber), 12);
+        assert_eq!(total_part_numbers(&lines, PartCounting::PerSymbol), 24);
+
+        // Symbols above and below
+        let lines = parse_lines(".*..\n.57.\n...#");
+        assert_eq!(total_part_numbers(&lines, PartCounting::PerNumber), 57);
+        assert_eq!(total_part_numbers(&lines, PartCounting::PerSymbol), 114);
+
+        // Surrounded, plus one number with no symbols at all
+        let lines = parse_lines("$+%....\n-8=..3.\n/&@....");
+        assert_eq!(total_part_numbers(&lines, PartCounting::PerNumber), 8);
+        assert_eq!(total_part_numbers(&lines, PartCounting::PerSymbol), 64);
+    }
+
     #[test]
     fn test_pt_2() {
         let lines = sample_lines();
-        let total = total_gear_ratios(&lines);
+        let total = total_gear_ratios(&lines, &puzzle_rule());
         assert_eq!(total, 467835);
     }
+
+    #[test]
+    fn test_gear_rules() {
+        let lines = sample_lines();
+        let gears = find_gears(&lines, &puzzle_rule());
+        let found: Vec<_> = gears
+            .iter()
+            .map(|g| (g.row, g.column, g.neighbors.clone()))
+            .collect();
+        assert_eq!(found, [(1, 3, vec![467, 35]), (8, 5, vec![755, 598])]);
+
+        let summed = GearRule {
+            ratio: sum,
+            ..puzzle_rule()
+        };
+        assert_eq!(total_gear_ratios(&lines, &summed), 467 + 35 + 755 + 598);
+
+        // Also take the lone 617 by the '*' on row 4, and 633 by the '#'
+        let loose = GearRule {
+            symbols: vec!['*', '#'],
+            neighbors: 1..=2,
+            ratio: product,
+        };
+        assert_eq!(total_gear_ratios(&lines, &loose), 467835 + 617 + 633);
+
+        let custom = GearRule {
+            ratio: |nums| nums.iter().max().copied().unwrap_or(0),
+            ..puzzle_rule()
+        };
+        assert_eq!(total_gear_ratios(&lines, &custom), 467 + 755);
+    }
+
+    #[test]
+    fn test_parse_neighbor_range() {
+        assert_eq!(parse_neighbor_range("2"), Ok(2..=2));
+        assert_eq!(parse_neighbor_range("1..=3"), Ok(1..=3));
+        assert!(parse_neighbor_range("1..3").is_err());
+    }
+
+    #[test]
+    fn test_faithful_grid() {
+        // Rows without any '.' are kept, and so are the columns of leading spaces
+        let lines = parse_lines("1234\n#*$%\n  56\n....");
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines.iter().map(|l| l.row).collect::<Vec<_>>(),
+            [0, 1, 2, 3]
+        );
+        assert_eq!(lines[0].numbers.len(), 1);
+        assert_eq!(lines[0].numbers[0].number, 1234);
+        assert_eq!(lines[1].symbols.len(), 4);
+        assert_eq!(lines[1].numbers.len(), 0);
+        assert_eq!(lines[2].symbols.len(), 0);
+        assert_eq!(lines[2].numbers[0].first_col, 2);
+        assert_eq!(lines[2].numbers[0].last_col, 3);
+        assert_eq!(
+            total_part_numbers(&lines, PartCounting::PerNumber),
+            1234 + 56
+        );
+    }
+
+    #[test]
+    fn test_schematic_errors() {
+        assert_eq!(
+            parse_schematic(["....", "..1", "...."]).err(),
+            Some(SchematicError::RaggedRow {
+                row: 1,
+                width: 3,
+                expected: 4
+            })
+        );
+        assert_eq!(
+            parse_schematic(["..", "", ".."]).err(),
+            Some(SchematicError::RaggedRow {
+                row: 1,
+                width: 0,
+                expected: 2
+            })
+        );
+        assert_eq!(
+            parse_schematic([".99999999999999999999999"]).err(),
+            Some(SchematicError::NumberTooLarge { row: 0, column: 1 })
+        );
+    }
+
+    fn random_row(rng: &mut StdRng, width: usize) -> String {
+        let mut row = String::new();
+        while row.len() < width {
+            match rng.gen_range(0..10) {
+                0..=5 => row.push('.'),
+                6..=7 => row.push_str(&format!("{}.", rng.gen_range(1..1000))),
+                _ => row.push(['*', '#', '$', '+'][rng.gen_range(0..4)]),
+            }
+        }
+        row.truncate(width);
+        row
+    }
+
+    // The original approach, checking every number against every symbol in the
+    // neighboring rows.
+    fn naive_totals(lines: &[Line], rule: &GearRule) -> (usize, usize, usize) {
+        let (mut per_number, mut per_symbol, mut gears) = (0, 0, 0);
+        for (idx, cur) in lines.iter().enumerate() {
+            let nearby = &lines[idx.saturating_sub(1)..(idx + 2).min(lines.len())];
+            let adjacent = |sym: &Symbol, num: &Number| {
+                sym.column + 1 >= num.first_col && sym.column <= num.last_col + 1
+            };
+            for num in cur.numbers.iter() {
+                let touching = nearby
+                    .iter()
+                    .flat_map(|l| l.symbols.iter())
+                    .filter(|sym| adjacent(sym, num))
+                    .count();
+                per_symbol += num.number * touching;
+                if touching > 0 {
+                    per_number += num.number;
+                }
+            }
+            for sym in cur.symbols.iter() {
+                if !rule.symbols.contains(&sym.symbol) {
+                    continue;
+                }
+                let neighbors: Vec<usize> = nearby
+                    .iter()
+                    .flat_map(|l| l.numbers.iter())
+                    .filter(|num| adjacent(sym, num))
+                    .map(|num| num.number)
+                    .collect();
+                if rule.neighbors.contains(&neighbors.len()) {
+                    gears += (rule.ratio)(&neighbors);
+                }
+            }
+        }
+        (per_number, per_symbol, gears)
+    }
+
+    fn indexed_totals(lines: &[Line], rule: &GearRule) -> (usize, usize, usize) {
+        (
+            total_part_numbers(lines, PartCounting::PerNumber),
+            total_part_numbers(lines, PartCounting::PerSymbol),
+            total_gear_ratios(lines, rule),
+        )
+    }
+
+    #[test]
+    fn test_index_matches_naive() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let rule = GearRule {
+            symbols: vec!['*', '#'],
+            neighbors: 1..=3,
+            ratio: product,
+        };
+        for _ in 0..20 {
+            let width = rng.gen_range(1..60);
+            let height = rng.gen_range(1..60);
+            let lines = parse_schematic((0..height).map(|_| random_row(&mut rng, width))).unwrap();
+            assert_eq!(indexed_totals(&lines, &rule), naive_totals(&lines, &rule));
+        }
+    }
+
+    #[test]
+    fn test_stream_matches_in_memory() {
+        let rule = puzzle_rule();
+        let rows: Vec<&str> = EXAMPLE_1.lines().map(|l| l.trim()).collect();
+        let totals = stream_totals(rows.iter().copied(), PartCounting::PerNumber, &rule);
+        assert_eq!(totals, Ok((4361, 467835)));
+        assert_eq!(
+            stream_totals(["12*3"], PartCounting::PerSymbol, &rule),
+            Ok((15, 36))
+        );
+        assert_eq!(
+            stream_totals(Vec::<&str>::new(), PartCounting::PerNumber, &rule),
+            Ok((0, 0))
+        );
+        assert_eq!(
+            stream_totals(["...", "1*"], PartCounting::PerNumber, &rule),
+            Err(SchematicError::RaggedRow {
+                row: 1,
+                width: 2,
+                expected: 3
+            })
+        );
+
+        let mut rng = StdRng::seed_from_u64(35);
+        for _ in 0..20 {
+            let width = rng.gen_range(1..60);
+            let height = rng.gen_range(1..60);
+            let rows: Vec<String> = (0..height).map(|_| random_row(&mut rng, width)).collect();
+            let lines = parse_schematic(rows.iter().map(|r| r.as_str())).unwrap();
+            for counting in [PartCounting::PerNumber, PartCounting::PerSymbol] {
+                let in_memory = (
+                    total_part_numbers(&lines, counting),
+                    total_gear_ratios(&lines, &rule),
+                );
+                let streamed = stream_totals(rows.iter().map(|r| r.as_str()), counting, &rule);
+                assert_eq!(streamed, Ok(in_memory));
+            }
+        }
+    }
+
+    #[test]
+    fn test_highlights() {
+        let lines = sample_lines();
+        let rule = puzzle_rule();
+        let runs = highlighted_runs(&lines, &rule);
+        assert_eq!(
+            runs[0],
+            [
+                (Highlight::Part, "467".to_owned()),
+                (Highlight::Plain, "..".to_owned()),
+                (Highlight::NotPart, "114".to_owned()),
+                (Highlight::Plain, "..".to_owned()),
+            ]
+        );
+        assert_eq!(runs[4][1], (Highlight::Symbol, "*".to_owned()));
+        assert_eq!(runs[8][3], (Highlight::Gear, "*".to_owned()));
+
+        let lines = parse_lines("<1#\n...");
+        assert_eq!(
+            render_ansi(&lines, &rule).lines().next(),
+            Some("\x1b[36m<\x1b[0m\x1b[1;35m1\x1b[0m\x1b[36m#\x1b[0m")
+        );
+        assert!(render_html(&lines, &rule)
+            .contains("<span class=\"symbol\">&lt;</span><span class=\"multi-part\">1</span>"));
+    }
+
+    // cargo test --release bench_10k_schematic -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_10k_schematic() {
+        let size = 10_000;
+        let mut rng = StdRng::seed_from_u64(10_000);
+        let start = Instant::now();
+        let lines = parse_schematic((0..size).map(|_| random_row(&mut rng, size))).unwrap();
+        println!("Parsed {size}x{size} schematic in {:?}", start.elapsed());
+
+        let rule = puzzle_rule();
+        let start = Instant::now();
+        let totals = indexed_totals(&lines, &rule);
+        println!("Indexed: {totals:?} in {:?}", start.elapsed());
+
+        // The naive approach is far too slow for the whole thing.
+        let rows = 100;
+        let start = Instant::now();
+        let totals = naive_totals(&lines[..rows], &rule);
+        println!(
+            "Naive, first {rows} rows: {totals:?} in {:?}",
+            start.elapsed()
+        );
+        let start = Instant::now();
+        let totals = indexed_totals(&lines[..rows], &rule);
+        println!(
+            "Indexed, first {rows} rows: {totals:?} in {:?}",
+            start.elapsed()
+        );
+    }
 }
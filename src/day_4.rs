@@ -1,91 +1,287 @@
 use lazy_static::lazy_static;
+use num::{BigUint, CheckedAdd, One, Zero};
 use regex::Regex;
+use std::borrow::Borrow;
 use std::collections::HashSet;
+use std::fmt::{self, Write};
 use std::fs::File;
 use std::io::{self, BufRead};
 
 const INPUT: &str = "inputs/input_4.txt";
 
-pub fn main() {
+#[derive(clap::Args)]
+pub struct Args {
+    /// Explain where each card's copies came from
+    #[arg(long, value_enum)]
+    explain: Option<Explain>,
+    /// How many levels of contributors to show in the tree
+    #[arg(long, default_value_t = 2)]
+    explain_depth: usize,
+    /// Count with arbitrary-precision integers instead of failing on overflow
+    #[arg(long)]
+    big: bool,
+    /// How a card's matches turn into points
+    #[arg(long, value_enum, default_value_t = Scoring::Doubling)]
+    scoring: Scoring,
+    /// Which cards a card wins copies of
+    #[arg(long, value_enum, default_value_t = Distribution::NextN)]
+    distribution: Distribution,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, clap::ValueEnum)]
+enum Scoring {
+    /// One point per match
+    Linear,
+    /// One point for the first match, doubled for each match after that
+    Doubling,
+    /// 1, 1, 2, 3, 5, ... points for 1, 2, 3, 4, 5, ... matches
+    Fibonacci,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, clap::ValueEnum)]
+enum Distribution {
+    /// The next N cards, for N matches, stopping at the end of the pile
+    NextN,
+    /// The previous N cards, stopping at the start of the pile
+    PreviousN,
+    /// The next N cards, wrapping around to the start of the pile
+    Wraparound,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, clap::ValueEnum)]
+enum Explain {
+    /// One row per card, listing the cards that won copies of it
+    Table,
+    /// Each card's contributors, and their contributors in turn
+    Tree,
+}
+
+pub fn main(args: &Args) {
     let file = File::open(INPUT).unwrap();
-    let mut cards = Vec::new();
-    for line in io::BufReader::new(file).lines() {
-        let line = line.unwrap();
-        let card = Card::from_line(&line);
-        if let Some(card) = card {
-            cards.push(card);
-        } else {
-            println!("Cannot parse line {line}");
+    let lines = io::BufReader::new(file).lines().map(|l| l.unwrap());
+    let cards = match parse_cards(lines) {
+        Ok(cards) => cards,
+        Err(errors) => {
+            for e in errors {
+                println!("{INPUT}: {e}");
+            }
+            return;
         }
+    };
+    let result = if args.big {
+        solve::<BigUint>(&cards, args)
+    } else {
+        solve::<usize>(&cards, args)
+    };
+    if let Err(e) = result {
+        println!("{e}");
     }
-    let point_ttl: usize = cards.iter().map(|c| c.score()).sum();
+}
+
+fn solve<T: Count>(cards: &[Card], args: &Args) -> Result<(), Overflow> {
+    let scores = cards.iter().map(|c| c.score(args.scoring));
+    let point_ttl: T = checked_sum(scores, Overflow::Total)?;
     println!("Part 1: Total points: {point_ttl}");
 
-    win_copies(&mut cards[..]);
-    let ttl_cards: usize = cards.iter().map(|c| c.copies).sum();
+    let cascade: Cascade<T> = win_copies(cards, args.distribution)?;
+    match args.explain {
+        Some(Explain::Table) => print!("{}", cascade_table(cards, &cascade)),
+        Some(Explain::Tree) => print!("{}", cascade_tree(cards, &cascade, args.explain_depth)),
+        None => (),
+    }
+    let ttl_cards: T = checked_sum(cascade.copies.iter().cloned().map(Ok), Overflow::Total)?;
     println!("Part 2: Total scratchcards: {ttl_cards}");
+    Ok(())
+}
+
+// Integers that card counts and points can be kept in: a machine integer,
+// where overflow is an error, or a BigUint, which never overflows.
+trait Count: Clone + fmt::Display + Zero + One + CheckedAdd {}
+
+impl<T: Clone + fmt::Display + Zero + One + CheckedAdd> Count for T {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Overflow {
+    // Card numbers, not indices
+    Score(usize),
+    Copies(usize),
+    Total,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Overflow::Score(num) => write!(f, "Card {num}'s score overflows; try --big"),
+            Overflow::Copies(num) => write!(f, "Copies of card {num} overflow; try --big"),
+            Overflow::Total => write!(f, "Total overflows; try --big"),
+        }
+    }
+}
+
+fn checked_sum<T: Count, I>(values: I, overflow: Overflow) -> Result<T, Overflow>
+where
+    I: Iterator<Item = Result<T, Overflow>>,
+{
+    let mut total = T::zero();
+    for value in values {
+        total = total.checked_add(&value?).ok_or_else(|| overflow.clone())?;
+    }
+    Ok(total)
+}
+
+#[derive(Debug, PartialEq)]
+struct CardError {
+    line: usize,
+    kind: CardErrorKind,
+}
+
+#[derive(Debug, PartialEq)]
+enum CardErrorKind {
+    Unparsable,
+    OutOfSequence {
+        expected: usize,
+        found: usize,
+    },
+    DuplicateWinning(usize),
+    DuplicateHave(usize),
+    // Counts of winning numbers and numbers you have, compared to the first card
+    InconsistentCounts {
+        counts: (usize, usize),
+        expected: (usize, usize),
+    },
+}
+
+impl fmt::Display for CardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            CardErrorKind::Unparsable => write!(f, "expected \"Card <n>: <numbers> | <numbers>\""),
+            CardErrorKind::OutOfSequence { expected, found } => {
+                write!(f, "expected card {expected}, found card {found}")
+            }
+            CardErrorKind::DuplicateWinning(n) => write!(f, "winning number {n} is repeated"),
+            CardErrorKind::DuplicateHave(n) => write!(f, "number {n} is repeated"),
+            CardErrorKind::InconsistentCounts { counts, expected } => write!(
+                f,
+                "card has {} winning numbers and {} numbers, but the first card has {} and {}",
+                counts.0, counts.1, expected.0, expected.1
+            ),
+        }
+    }
+}
+
+// Parses and checks every card, skipping blank lines. Cards must be numbered
+// consecutively from 1, must not repeat a number within either list, and must
+// all have the same number of winning numbers and numbers as the first card.
+// Reports every problem found, not just the first.
+fn parse_cards<I>(lines: I) -> Result<Vec<Card>, Vec<CardError>>
+where
+    I: IntoIterator,
+    I::Item: Borrow<str>,
+{
+    let mut cards: Vec<Card> = Vec::new();
+    let mut errors = Vec::new();
+    let mut expected_counts = None;
+    // Follows every card number that can be read, even on cards rejected for
+    // something else, so that one bad card doesn't put the rest out of sequence
+    let mut expected_num = 1;
+    for (idx, line) in lines.into_iter().enumerate() {
+        let line_num = idx + 1;
+        let line = line.borrow();
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut error = |kind| {
+            errors.push(CardError {
+                line: line_num,
+                kind,
+            })
+        };
+        let card = match Card::from_line(line) {
+            Ok(card) => card,
+            Err(kind) => {
+                error(kind);
+                if let Some(num) = card_number(line) {
+                    expected_num = num + 1;
+                }
+                continue;
+            }
+        };
+        if card.num != expected_num {
+            error(CardErrorKind::OutOfSequence {
+                expected: expected_num,
+                found: card.num,
+            });
+        }
+        expected_num = card.num + 1;
+        let counts = (card.winning_nums.len(), card.have_nums.len());
+        let expected = *expected_counts.get_or_insert(counts);
+        if counts != expected {
+            error(CardErrorKind::InconsistentCounts { counts, expected });
+        }
+        cards.push(card);
+    }
+    if errors.is_empty() {
+        Ok(cards)
+    } else {
+        Err(errors)
+    }
+}
+
+// Whitespace-separated numbers, or None if any aren't numbers
+fn parse_numbers(s: &str) -> Option<Vec<usize>> {
+    s.split_whitespace().map(|n| n.parse().ok()).collect()
 }
 
-#[allow(dead_code)]
+// The first number appearing more than once
+fn first_repeat(nums: &[usize]) -> Option<usize> {
+    let mut seen = HashSet::new();
+    nums.iter().find(|n| !seen.insert(**n)).copied()
+}
+
+#[derive(Debug)]
 struct Card {
     num: usize,
     winning_nums: HashSet<usize>,
     have_nums: Vec<usize>,
-    copies: usize,
+}
+
+// The number of a card line that may not parse otherwise
+fn card_number(line: &str) -> Option<usize> {
+    lazy_static! {
+        static ref CARD_NUM_RE: Regex = Regex::new(r"^\s*Card +(\d+)\b").unwrap();
+    }
+    CARD_NUM_RE.captures(line)?[1].parse().ok()
 }
 
 impl Card {
-    fn from_line(line: &str) -> Option<Card> {
+    fn from_line(line: &str) -> Result<Card, CardErrorKind> {
         lazy_static! {
-            static ref OK_LINE_RE: Regex = Regex::new(r"Card +(\d+):([\d ]+)\|([\d ]+)").unwrap();
+            static ref OK_LINE_RE: Regex =
+                Regex::new(r"^\s*Card +(\d+):([\d ]+)\|([\d ]+)$").unwrap();
         }
 
-        let caps = OK_LINE_RE.captures(line);
-        if let Some(caps) = caps {
-            let num: usize = caps.get(1).unwrap().as_str().parse().unwrap();
-            let winning_nums: HashSet<usize> = caps
-                .get(2)
-                .unwrap()
-                .as_str()
-                .trim()
-                .split(' ')
-                .filter_map(|s| {
-                    if s.len() > 0 {
-                        Some(s.parse().unwrap())
-                    } else {
-                        None
-                    }
-                })
-                .collect();
-            let have_nums: Vec<usize> = caps
-                .get(3)
-                .unwrap()
-                .as_str()
-                .trim()
-                .split(' ')
-                .filter_map(|s| {
-                    if s.len() > 0 {
-                        Some(s.parse().unwrap())
-                    } else {
-                        None
-                    }
-                })
-                .collect();
-            Some(Card {
-                num,
-                winning_nums,
-                have_nums,
-                copies: 1,
-            })
-        } else {
-            None
+        let caps = OK_LINE_RE.captures(line).ok_or(CardErrorKind::Unparsable)?;
+        let num: usize = caps[1].parse().map_err(|_| CardErrorKind::Unparsable)?;
+        let winning_nums = parse_numbers(&caps[2]).ok_or(CardErrorKind::Unparsable)?;
+        let have_nums = parse_numbers(&caps[3]).ok_or(CardErrorKind::Unparsable)?;
+        if let Some(n) = first_repeat(&winning_nums) {
+            return Err(CardErrorKind::DuplicateWinning(n));
+        }
+        if let Some(n) = first_repeat(&have_nums) {
+            return Err(CardErrorKind::DuplicateHave(n));
         }
+        Ok(Card {
+            num,
+            winning_nums: winning_nums.into_iter().collect(),
+            have_nums,
+        })
     }
 
-    fn num_matches(&self) -> i32 {
-        let mut matches: i32 = 0;
+    fn num_matches(&self) -> usize {
+        let mut matches = 0;
         for num in self.have_nums.iter() {
-            if self.winning_nums.contains(&num) {
+            if self.winning_nums.contains(num) {
                 matches += 1;
                 // println!("{num} is a winning number!");
             }
@@ -93,28 +289,149 @@ impl Card {
         matches
     }
 
-    fn score(&self) -> usize {
-        let matches = self.num_matches();
-        if matches > 0 {
-            2usize.pow((matches - 1) as u32)
-        } else {
-            0
-        }
+    fn score<T: Count>(&self, scoring: Scoring) -> Result<T, Overflow> {
+        scoring.points(self.num_matches(), self.num)
     }
 }
 
-fn win_copies(cards: &mut [Card]) {
-    for idx in 0..cards.len() {
-        let matches = cards[idx].num_matches() as usize;
-        for idx2 in idx + 1..idx + matches + 1 {
-            if idx2 >= cards.len() {
-                break;
+impl Scoring {
+    fn points<T: Count>(&self, matches: usize, card_num: usize) -> Result<T, Overflow> {
+        if matches == 0 {
+            return Ok(T::zero());
+        }
+        let overflow = || Overflow::Score(card_num);
+        let mut points = T::one();
+        match self {
+            Scoring::Linear => {
+                for _ in 1..matches {
+                    points = points.checked_add(&T::one()).ok_or_else(overflow)?;
+                }
+            }
+            Scoring::Doubling => {
+                for _ in 1..matches {
+                    points = points.checked_add(&points).ok_or_else(overflow)?;
+                }
+            }
+            Scoring::Fibonacci => {
+                let mut prev = T::zero();
+                for _ in 1..matches {
+                    let next = points.checked_add(&prev).ok_or_else(overflow)?;
+                    prev = points;
+                    points = next;
+                }
             }
-            cards[idx2].copies += cards[idx].copies;
         }
+        Ok(points)
     }
 }
 
+impl Distribution {
+    // Indices of the cards that the card at `idx` wins copies of. A card never
+    // wins copies of itself, even when it has more matches than there are cards.
+    fn targets(&self, idx: usize, matches: usize, len: usize) -> Vec<usize> {
+        match self {
+            Distribution::NextN => (idx + 1..len.min(idx + matches + 1)).collect(),
+            Distribution::PreviousN => (idx.saturating_sub(matches)..idx).rev().collect(),
+            Distribution::Wraparound => (1..=matches.min(len - 1))
+                .map(|offset| (idx + offset) % len)
+                .collect(),
+        }
+    }
+
+    // The order cards hand out their copies in. Cards only ever win copies of
+    // cards after them in this order, except with wraparound: copies won from
+    // a card later in the pile come too late to be handed on again.
+    fn order(&self, len: usize) -> Vec<usize> {
+        match self {
+            Distribution::NextN | Distribution::Wraparound => (0..len).collect(),
+            Distribution::PreviousN => (0..len).rev().collect(),
+        }
+    }
+}
+
+// The result of the part 2 cascade
+struct Cascade<T> {
+    // How many of each card there are, originals included
+    copies: Vec<T>,
+    // Indices of the cards that won copies of each card, and how many
+    won_from: Vec<Vec<(usize, T)>>,
+}
+
+fn win_copies<T: Count>(
+    cards: &[Card],
+    distribution: Distribution,
+) -> Result<Cascade<T>, Overflow> {
+    let mut copies = vec![T::one(); cards.len()];
+    let mut won_from = vec![Vec::new(); cards.len()];
+    for idx in distribution.order(cards.len()) {
+        let matches = cards[idx].num_matches();
+        for idx2 in distribution.targets(idx, matches, cards.len()) {
+            let won = copies[idx].clone();
+            copies[idx2] = copies[idx2]
+                .checked_add(&won)
+                .ok_or(Overflow::Copies(cards[idx2].num))?;
+            won_from[idx2].push((idx, won));
+        }
+    }
+    Ok(Cascade { copies, won_from })
+}
+
+// Card | Copies | Won from
+// Every card starts with its one original; the rest are listed by source.
+fn cascade_table<T: Count>(cards: &[Card], cascade: &Cascade<T>) -> String {
+    let mut out = String::from("Card | Copies | Won from\n");
+    for (idx, card) in cards.iter().enumerate() {
+        let sources: Vec<String> = cascade.won_from[idx]
+            .iter()
+            .map(|(idx, won)| format!("{} from card {}", won, cards[*idx].num))
+            .collect();
+        let sources = if sources.is_empty() {
+            "-".to_owned()
+        } else {
+            sources.join(", ")
+        };
+        let copies = cascade.copies[idx].to_string();
+        writeln!(out, "{:>4} | {:>6} | {sources}", card.num, copies).unwrap();
+    }
+    out
+}
+
+// Lists each card's contributors, expanding theirs in turn down to `depth`
+// levels. Every card's count is its original plus what it won.
+fn cascade_tree<T: Count>(cards: &[Card], cascade: &Cascade<T>, depth: usize) -> String {
+    fn add_sources<T: Count>(
+        out: &mut String,
+        cards: &[Card],
+        cascade: &Cascade<T>,
+        idx: usize,
+        indent: usize,
+        depth: usize,
+    ) {
+        if indent > depth {
+            return;
+        }
+        for (source, won) in cascade.won_from[idx].iter() {
+            writeln!(
+                out,
+                "{:indent$}+{won} from card {}",
+                "",
+                cards[*source].num,
+                indent = indent * 2
+            )
+            .unwrap();
+            add_sources(out, cards, cascade, *source, indent + 1, depth);
+        }
+    }
+
+    let mut out = String::new();
+    for (idx, card) in cards.iter().enumerate() {
+        writeln!(out, "Card {}: {} in total", card.num, cascade.copies[idx]).unwrap();
+        writeln!(out, "  1 original").unwrap();
+        add_sources(&mut out, cards, cascade, idx, 1, depth);
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -126,25 +443,240 @@ mod tests {
     Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36
     Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11"#;
 
+    fn example_cards() -> Vec<Card> {
+        parse_cards(EXAMPLE_1.lines()).unwrap()
+    }
+
     #[test]
     fn test_score() {
-        let results = vec![8, 2, 2, 1, 0, 0];
+        let results = [8, 2, 2, 1, 0, 0];
         for (line, expected_score) in zip(EXAMPLE_1.lines(), results.iter()) {
             let card = Card::from_line(line).unwrap();
-            assert_eq!(card.score(), *expected_score);
+            assert_eq!(card.score(Scoring::Doubling), Ok(*expected_score));
         }
     }
 
     #[test]
     fn test_copies() {
-        let results = vec![1, 2, 4, 8, 14, 1];
-        let mut cards: Vec<Card> = EXAMPLE_1
+        let results = [1, 2, 4, 8, 14, 1];
+        let cascade: Cascade<usize> = win_copies(&example_cards(), Distribution::NextN).unwrap();
+        for (copies, expected_count) in zip(cascade.copies, results) {
+            assert_eq!(copies, expected_count);
+        }
+    }
+
+    #[test]
+    fn test_provenance() {
+        let cascade: Cascade<usize> = win_copies(&example_cards(), Distribution::NextN).unwrap();
+        for (copies, won_from) in zip(&cascade.copies, &cascade.won_from) {
+            let won: usize = won_from.iter().map(|(_, won)| won).sum();
+            assert_eq!(*copies, 1 + won);
+        }
+        assert_eq!(cascade.won_from[0], []);
+        assert_eq!(cascade.won_from[4], [(0, 1), (2, 4), (3, 8)]);
+        assert_eq!(cascade.won_from[5], []);
+    }
+
+    #[test]
+    fn test_explain() {
+        let cards = example_cards();
+        let cascade: Cascade<usize> = win_copies(&cards, Distribution::NextN).unwrap();
+        let table = cascade_table(&cards, &cascade);
+        assert_eq!(
+            table.lines().nth(5),
+            Some("   5 |     14 | 1 from card 1, 4 from card 3, 8 from card 4")
+        );
+        assert_eq!(table.lines().nth(6), Some("   6 |      1 | -"));
+        assert!(!cascade_tree(&cards, &cascade, 1).contains("    +"));
+
+        let tree = cascade_tree(&cards, &cascade, 2);
+        let card_3: Vec<&str> = tree
             .lines()
-            .filter_map(|l| Card::from_line(l))
+            .skip_while(|l| *l != "Card 3: 4 in total")
+            .take(6)
             .collect();
-        win_copies(&mut cards[..]);
-        for (card, expected_count) in zip(cards, results) {
-            assert_eq!(card.copies, expected_count);
+        assert_eq!(
+            card_3,
+            [
+                "Card 3: 4 in total",
+                "  1 original",
+                "  +1 from card 1",
+                "  +2 from card 2",
+                "    +1 from card 1",
+                "Card 4: 8 in total",
+            ]
+        );
+    }
+
+    // A card with every one of its numbers winning
+    fn card_with_matches(num: usize, matches: usize) -> Card {
+        Card {
+            num,
+            winning_nums: (1..=matches).collect(),
+            have_nums: (1..=matches).collect(),
         }
     }
+
+    #[test]
+    fn test_overflow() {
+        let card = card_with_matches(1, 64);
+        assert_eq!(card.score::<u64>(Scoring::Doubling), Ok(1 << 63));
+        let card = card_with_matches(2, 65);
+        assert_eq!(
+            card.score::<u64>(Scoring::Doubling),
+            Err(Overflow::Score(2))
+        );
+        assert_eq!(
+            card.score::<BigUint>(Scoring::Doubling),
+            Ok(BigUint::from(1u64 << 63) * 2u32)
+        );
+
+        // Each card wins a copy of every later card, so card n has 2^(n-1) copies.
+        let cards: Vec<Card> = (1..=70)
+            .map(|num| card_with_matches(num, 70 - num))
+            .collect();
+        assert_eq!(
+            win_copies::<u64>(&cards, Distribution::NextN).err(),
+            Some(Overflow::Copies(65))
+        );
+        let cascade: Cascade<BigUint> = win_copies(&cards, Distribution::NextN).unwrap();
+        assert_eq!(cascade.copies[69], BigUint::one() << 69);
+        let total: BigUint =
+            checked_sum(cascade.copies.into_iter().map(Ok), Overflow::Total).unwrap();
+        assert_eq!(total, (BigUint::one() << 70) - 1u32);
+
+        let totals = [Ok(u64::MAX), Ok(1)];
+        assert_eq!(
+            checked_sum(totals.into_iter(), Overflow::Total),
+            Err(Overflow::Total)
+        );
+    }
+
+    #[test]
+    fn test_scoring_rules() {
+        let cards = example_cards();
+        let total = |scoring| -> usize {
+            cards
+                .iter()
+                .map(|c| c.score::<usize>(scoring).unwrap())
+                .sum()
+        };
+        assert_eq!(total(Scoring::Linear), 4 + 2 + 2 + 1);
+        assert_eq!(total(Scoring::Doubling), 13);
+        assert_eq!(total(Scoring::Fibonacci), 3 + 1 + 1 + 1);
+
+        let points: Vec<u32> = (0..8)
+            .map(|m| Scoring::Fibonacci.points(m, 1).unwrap())
+            .collect();
+        assert_eq!(points, [0, 1, 1, 2, 3, 5, 8, 13]);
+        assert_eq!(Scoring::Linear.points::<u8>(255, 1), Ok(255));
+        assert_eq!(
+            Scoring::Linear.points::<u8>(256, 1),
+            Err(Overflow::Score(1))
+        );
+    }
+
+    #[test]
+    fn test_distribution_rules() {
+        let copies =
+            |cards: &[Card], distribution| win_copies::<usize>(cards, distribution).unwrap().copies;
+        let cards = example_cards();
+        assert_eq!(copies(&cards, Distribution::NextN), [1, 2, 4, 8, 14, 1]);
+        assert_eq!(copies(&cards, Distribution::PreviousN), [6, 3, 2, 1, 1, 1]);
+        assert_eq!(
+            copies(&cards, Distribution::Wraparound),
+            [1, 2, 4, 8, 14, 1]
+        );
+
+        // The last card's copies wrap around to the first two
+        let cards: Vec<Card> = [0, 1, 2]
+            .iter()
+            .enumerate()
+            .map(|(i, m)| card_with_matches(i + 1, *m))
+            .collect();
+        assert_eq!(copies(&cards, Distribution::Wraparound), [3, 3, 2]);
+        assert_eq!(copies(&cards, Distribution::NextN), [1, 1, 2]);
+        assert_eq!(copies(&cards, Distribution::PreviousN), [4, 2, 1]);
+
+        // More matches than other cards
+        let cards: Vec<Card> = [5, 0, 0]
+            .iter()
+            .enumerate()
+            .map(|(i, m)| card_with_matches(i + 1, *m))
+            .collect();
+        assert_eq!(copies(&cards, Distribution::Wraparound), [1, 2, 2]);
+    }
+
+    #[test]
+    fn test_validation_rejected_card_in_sequence() {
+        let errors = parse_cards([
+            "Card 1: 1 2 | 3 4 5",
+            "Card 2: 1 1 | 3 4 5",
+            "Card 3: 1 2 | 3 4 5",
+        ])
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            [CardError {
+                line: 2,
+                kind: CardErrorKind::DuplicateWinning(1)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_validation() {
+        let errors = parse_cards([
+            "Card 1: 1 2 | 3 4 5",
+            "Card 2: 1 1 | 3 4 5",
+            "Card 2: 1 2 | 3 4 4",
+            "",
+            "Card 4: 1 2 | 3 4",
+            "Card 5 1 2 | 3 4 5",
+            "Card 6: 1 2 | 3 4 5",
+            "Card 7: 1 2 | 3 99999999999999999999999",
+        ])
+        .unwrap_err();
+        let kinds: Vec<(usize, CardErrorKind)> =
+            errors.into_iter().map(|e| (e.line, e.kind)).collect();
+        assert_eq!(
+            kinds,
+            [
+                (2, CardErrorKind::DuplicateWinning(1)),
+                (3, CardErrorKind::DuplicateHave(4)),
+                (
+                    5,
+                    CardErrorKind::OutOfSequence {
+                        expected: 3,
+                        found: 4
+                    }
+                ),
+                (
+                    5,
+                    CardErrorKind::InconsistentCounts {
+                        counts: (2, 2),
+                        expected: (2, 3)
+                    }
+                ),
+                (6, CardErrorKind::Unparsable),
+                (8, CardErrorKind::Unparsable),
+            ]
+        );
+
+        let e = CardError {
+            line: 5,
+            kind: CardErrorKind::OutOfSequence {
+                expected: 2,
+                found: 4,
+            },
+        };
+        assert_eq!(e.to_string(), "line 5: expected card 2, found card 4");
+        assert!(parse_cards(["Card 2: 1 | 2"]).is_err());
+        assert_eq!(
+            parse_cards(["  Card  1: 1 | 2", "Card 2: 3 | 3"])
+                .unwrap()
+                .len(),
+            2
+        );
+    }
 }
@@ -1,110 +1,683 @@
 use crate::utils;
+use crate::utils::IntervalSet;
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::borrow::Borrow;
-use std::mem;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
 
 const INPUT: &str = "inputs/input_5.txt";
 
-pub fn main() {
-    let almanac = Almanac::from_file(utils::lines_in_file(INPUT)).unwrap();
-    let closest_seed = almanac.min_loc();
-    println!("Day 5 pt 1: Seed ID in closest location: {closest_seed}");
+#[derive(clap::Args)]
+pub struct Args {
+    /// Read the almanac from this file instead (as JSON if it ends in .json)
+    #[arg(long)]
+    load: Option<String>,
+    /// Save the almanac to a file (as JSON if it ends in .json)
+    #[arg(long)]
+    save: Option<String>,
+    /// Read the seeds line one way only, instead of as IDs for part 1 and
+    /// ranges for part 2
+    #[arg(long)]
+    seeds: Option<SeedMode>,
+    /// Print the seed-to-location map that all the maps compose into
+    #[arg(long)]
+    print_composed: bool,
+    /// Save the composed seed-to-location map to a file (as JSON if it ends
+    /// in .json)
+    #[arg(long)]
+    save_composed: Option<String>,
+    /// Answer from a composed seed-to-location map saved earlier, reading only
+    /// the seeds from the almanac
+    #[arg(
+        long,
+        conflicts_with_all = ["save", "seeds_at", "lowest_band", "fragments", "trace", "convert"]
+    )]
+    load_composed: Option<String>,
+    /// List every seed that ends up at this location
+    #[arg(long, value_name = "LOCATION")]
+    seeds_at: Option<usize>,
+    /// List the seed ranges that reach the lowest band of locations
+    #[arg(long)]
+    lowest_band: bool,
+    /// Show how many separate ranges of IDs the seed ranges reach in each category
+    #[arg(long)]
+    fragments: bool,
+    /// Show each step these seeds take from seed to location
+    #[arg(long, value_delimiter = ',')]
+    trace: Vec<usize>,
+    /// Map this ID from one category to another (see --from and --to)
+    #[arg(long)]
+    convert: Option<usize>,
+    /// Category to convert from
+    #[arg(long, default_value = "seed")]
+    from: String,
+    /// Category to convert to
+    #[arg(long, default_value = "location")]
+    to: String,
+}
+
+pub fn main(args: &Args) {
+    let source = args.load.as_deref().unwrap_or(INPUT);
+    let text = match fs::read_to_string(source) {
+        Ok(text) => text,
+        Err(e) => {
+            println!("{source}: {e}");
+            return;
+        }
+    };
+    if let Some(filename) = &args.load_composed {
+        main_composed(args, source, &text, filename);
+        return;
+    }
+    let parse = |mode| {
+        if is_json(source) {
+            Almanac::from_json(&text)
+        } else {
+            Almanac::from_file(text.lines(), mode)
+        }
+    };
+    let almanac = match parse(args.seeds.unwrap_or(SeedMode::Ranges)) {
+        Ok(almanac) => almanac,
+        Err(e) => {
+            println!("{source}: {e}");
+            return;
+        }
+    };
+    let issues = almanac.validate();
+    for (map, issue) in issues.iter() {
+        println!(
+            "{source}: {}-to-{} map: {issue}",
+            map.from_name, map.to_name
+        );
+    }
+    if issues.iter().any(|(_, issue)| issue.is_overflow()) {
+        return;
+    }
+    if let Some(filename) = &args.save {
+        if let Err(e) = fs::write(filename, almanac.save(is_json(filename))) {
+            println!("{filename}: {e}");
+            return;
+        }
+    }
+    if let Some(id) = args.convert {
+        match almanac.map_between(&args.from, &args.to, id) {
+            Ok(to_id) => println!("{} {id} is {} {to_id}", args.from, args.to),
+            Err(e) => println!("{source}: {e}"),
+        }
+    }
+    let composed = almanac.composed();
+    if !output_composed(args, &composed) {
+        return;
+    }
+    // A JSON almanac's seeds have already been read one way
+    if args.seeds.is_some() || is_json(source) {
+        let closest_seed = almanac.min_loc_ranges();
+        println!(
+            "Day 5: Closest location for seeds as {}: {closest_seed}",
+            almanac.seeds.kind()
+        );
+    } else {
+        // The same lines parsed fine as ranges, so they will as IDs
+        let closest_seed = parse(SeedMode::Ids).unwrap().seeds.min_loc(&composed);
+        println!("Day 5 pt 1: Seed ID in closest location: {closest_seed}");
+
+        let pt2_closest_seed = almanac.min_loc_ranges();
+        println!("Day 5 pt 2: Seed ID in closest location: {pt2_closest_seed}");
+    }
 
-    let pt2_closest_seed = almanac.min_loc_ranges();
-    println!("Day 5 pt 2: Seed ID in closest location: {pt2_closest_seed}");
+    if let Some(location) = args.seeds_at {
+        let seeds = almanac.unmap_final(location);
+        let seeds: Vec<String> = seeds.iter().map(|s| s.to_string()).collect();
+        println!("Seeds at location {location}: {}", seeds.join(", "));
+    }
+    for seed in args.trace.iter() {
+        print!("{}", almanac.trace(*seed));
+    }
+    if args.fragments {
+        for (category, ids) in almanac.stages() {
+            println!("{category}: {} ranges", ids.ranges().len());
+        }
+    }
+    if args.lowest_band {
+        for range in almanac.lowest_band_seeds().ranges() {
+            println!(
+                "Seeds {}..={} reach the lowest band",
+                range.start,
+                range.end - 1
+            );
+        }
+    }
 }
 
+// The composed map stands in for all of the almanac's maps, so only its seeds
+// are read.
+fn main_composed(args: &Args, source: &str, text: &str, filename: &str) {
+    let loaded = match fs::read_to_string(filename) {
+        Ok(text) => Map::load(&text, is_json(filename)).map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    let composed = match loaded {
+        Ok(map) => map,
+        Err(e) => {
+            println!("{filename}: {e}");
+            return;
+        }
+    };
+    if !output_composed(args, &composed) {
+        return;
+    }
+    let parse = |mode| Seeds::load(text, is_json(source), mode);
+    let seeds = match parse(args.seeds.unwrap_or(SeedMode::Ranges)) {
+        Ok(seeds) => seeds,
+        Err(e) => {
+            println!("{source}: {e}");
+            return;
+        }
+    };
+    if args.seeds.is_some() || is_json(source) {
+        let closest_seed = seeds.min_loc(&composed);
+        println!(
+            "Day 5: Closest location for seeds as {}: {closest_seed}",
+            seeds.kind()
+        );
+    } else {
+        let closest_seed = parse(SeedMode::Ids).unwrap().min_loc(&composed);
+        println!("Day 5 pt 1: Seed ID in closest location: {closest_seed}");
+
+        let pt2_closest_seed = seeds.min_loc(&composed);
+        println!("Day 5 pt 2: Seed ID in closest location: {pt2_closest_seed}");
+    }
+}
+
+// Prints and saves the composed map as asked. False if it couldn't be saved.
+fn output_composed(args: &Args, composed: &Map) -> bool {
+    if args.print_composed {
+        print!("{composed}");
+    }
+    if let Some(filename) = &args.save_composed {
+        if let Err(e) = fs::write(filename, composed.save(is_json(filename))) {
+            println!("{filename}: {e}");
+            return false;
+        }
+    }
+    true
+}
+
+// Files ending in .json hold JSON; anything else is in the almanac's own format
+fn is_json(filename: &str) -> bool {
+    filename.ends_with(".json")
+}
+
+// How to read the numbers on the seeds line
+#[derive(Clone, Copy, PartialEq, Debug, clap::ValueEnum)]
+enum SeedMode {
+    Ids,
+    // Pairs of start ID and length
+    Ranges,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Seeds {
+    Ids(Vec<usize>),
+    Ranges(Vec<Range<usize>>),
+}
+
+#[derive(Debug, PartialEq)]
+enum AlmanacError {
+    BadLine(usize),
+    OddSeedCount(usize),
+    // The seed range, numbered from 1, whose end is past the largest ID
+    SeedRangeOverflow(usize),
+    // A seed ID, numbered from 1, too large to have any IDs after it
+    SeedIdOverflow(usize),
+    // No seed IDs at all, or only empty ranges of them
+    NoSeeds,
+    Json(String),
+    // A loaded composed map that isn't from seed to location
+    NotSeedToLocation { from: String, to: String },
+    // A loaded composed map whose ranges can't be looked up by binary search
+    BadComposedMap(MapIssue),
+    Route(RouteError),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 struct Almanac {
-    seeds: Vec<usize>,
+    seeds: Seeds,
     maps: Vec<Map>,
+    // Indices of the maps from seed to location, found once when it's read
+    #[serde(skip)]
+    chain: Vec<usize>,
 }
 
-#[allow(dead_code)]
+#[derive(Debug, Serialize, Deserialize)]
 struct Map {
     from_name: String,
     to_name: String,
     ranges: Vec<MapRange>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
 struct MapRange {
     dest_id: usize,
     source_id: usize,
     range_length: usize,
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
-struct IDRange {
-    map_level: usize, // 0 = seed, 1 = soil, etc.
-    start_id: usize,
-    length: usize,
-    // sub_ranges: Option<Vec<IDRange>>,
+#[derive(Debug, PartialEq)]
+enum RouteError {
+    UnknownCategory(String),
+    NoRoute {
+        from: String,
+        to: String,
+    },
+    // Each route as the categories along it
+    Ambiguous {
+        from: String,
+        to: String,
+        routes: Vec<Vec<String>>,
+    },
+}
+
+// A seed's way from seed to location
+struct Trace<'a> {
+    seed: usize,
+    steps: Vec<Step<'a>>,
+}
+
+struct Step<'a> {
+    map: &'a Map,
+    from_id: usize,
+    to_id: usize,
+    // Index of the range that applied, or None if the ID mapped to itself by default
+    range: Option<usize>,
+}
+
+// Problems with a map's ranges, which are numbered from 1 in the order listed
+#[derive(Debug, PartialEq)]
+enum MapIssue {
+    // Only the first range listed applies where they overlap
+    OverlappingSources(usize, usize),
+    // Different IDs map to the same ID
+    OverlappingDestinations(usize, usize),
+    ZeroLength(usize),
+    SourceOverflow(usize),
+    DestinationOverflow(usize),
 }
 
 impl Almanac {
-    fn from_file<I>(lines: I) -> Option<Almanac>
+    fn from_file<I>(lines: I, mode: SeedMode) -> Result<Almanac, AlmanacError>
     where
         I: IntoIterator,
         I::Item: Borrow<str>,
     {
-        let mut lines = lines.into_iter();
-        let seeds = parse_seeds_line(lines.next()?.borrow())?;
-        lines.next()?;
+        let mut lines = lines.into_iter().enumerate().map(|(idx, l)| (idx + 1, l));
+        let (_, first) = lines.next().ok_or(AlmanacError::BadLine(1))?;
+        let numbers = parse_seeds_line(first.borrow()).ok_or(AlmanacError::BadLine(1))?;
+        let seeds = Seeds::new(numbers, mode)?;
+        lines.next().ok_or(AlmanacError::BadLine(2))?;
         let mut maps = Vec::new();
         let mut map: Option<Map> = None;
-        for line in lines {
+        for (line_num, line) in lines {
             let line = line.borrow().trim();
             if line.is_empty() && map.is_some() {
                 maps.push(map.take().unwrap()); // blank lines separate mappings
                 continue;
             }
+            let bad_line = AlmanacError::BadLine(line_num);
             if let Some(map) = &mut map {
                 // <dest> <source> <N>
-                map.ranges.push(MapRange::from_str(line)?);
+                map.ranges.push(MapRange::from_str(line).ok_or(bad_line)?);
             } else {
-                if map.is_some() {
-                    panic!("Line out of sequence, expect new map.")
-                }
-                map = Some(Map::from_str(line)?);
+                map = Some(Map::from_str(line).ok_or(bad_line)?);
             }
         }
         if map.is_some() {
             maps.push(map.take().unwrap());
         }
 
-        Some(Almanac { seeds, maps })
+        Almanac::new(seeds, maps)
+    }
+
+    fn from_json(text: &str) -> Result<Almanac, AlmanacError> {
+        let almanac: Almanac =
+            serde_json::from_str(text).map_err(|e| AlmanacError::Json(e.to_string()))?;
+        Almanac::new(almanac.seeds, almanac.maps)
+    }
+
+    fn new(seeds: Seeds, maps: Vec<Map>) -> Result<Almanac, AlmanacError> {
+        let mut almanac = Almanac {
+            seeds,
+            maps,
+            chain: Vec::new(),
+        };
+        almanac.seeds.check()?;
+        almanac.chain = almanac
+            .route_indices("seed", "location")
+            .map_err(AlmanacError::Route)?;
+        Ok(almanac)
+    }
+
+    fn save(&self, json: bool) -> String {
+        if json {
+            serde_json::to_string_pretty(self).unwrap()
+        } else {
+            self.to_string()
+        }
+    }
+
+    // The maps to apply, in order, to get from one category to another.
+    // Categories are linked by the map names, whatever order the maps are
+    // listed in, and there must be exactly one way through.
+    fn route(&self, from: &str, to: &str) -> Result<Vec<&Map>, RouteError> {
+        let route = self.route_indices(from, to)?;
+        Ok(route.iter().map(|idx| &self.maps[*idx]).collect())
+    }
+
+    // The same, as indices into `maps`
+    fn route_indices(&self, from: &str, to: &str) -> Result<Vec<usize>, RouteError> {
+        for name in [from, to] {
+            if !self
+                .maps
+                .iter()
+                .any(|m| m.from_name == name || m.to_name == name)
+            {
+                return Err(RouteError::UnknownCategory(name.to_owned()));
+            }
+        }
+        let mut routes = Vec::new();
+        self.find_routes(to, &mut vec![from], &mut Vec::new(), &mut routes);
+        match routes.len() {
+            0 => Err(RouteError::NoRoute {
+                from: from.to_owned(),
+                to: to.to_owned(),
+            }),
+            1 => Ok(routes.pop().unwrap()),
+            _ => Err(RouteError::Ambiguous {
+                from: from.to_owned(),
+                to: to.to_owned(),
+                routes: routes
+                    .iter()
+                    .map(|route| {
+                        let mut names = vec![from.to_owned()];
+                        names.extend(route.iter().map(|idx| self.maps[*idx].to_name.clone()));
+                        names
+                    })
+                    .collect(),
+            }),
+        }
+    }
+
+    // Depth-first search for every route to `to` that doesn't revisit a category
+    fn find_routes<'a>(
+        &'a self,
+        to: &str,
+        visited: &mut Vec<&'a str>,
+        route: &mut Vec<usize>,
+        routes: &mut Vec<Vec<usize>>,
+    ) {
+        let here = *visited.last().unwrap();
+        if here == to {
+            routes.push(route.clone());
+            return;
+        }
+        for (idx, map) in self.maps.iter().enumerate() {
+            if map.from_name != here {
+                continue;
+            }
+            if visited.contains(&map.to_name.as_str()) {
+                continue;
+            }
+            visited.push(&map.to_name);
+            route.push(idx);
+            self.find_routes(to, visited, route, routes);
+            route.pop();
+            visited.pop();
+        }
+    }
+
+    // The maps from seed to location
+    fn chain(&self) -> Vec<&Map> {
+        self.chain.iter().map(|idx| &self.maps[*idx]).collect()
     }
 
+    fn map_between(&self, from: &str, to: &str, id: usize) -> Result<usize, RouteError> {
+        let route = self.route(from, to)?;
+        Ok(route.iter().fold(id, |acc, m| m.map_from(acc)))
+    }
+
+    fn validate(&self) -> Vec<(&Map, MapIssue)> {
+        self.maps
+            .iter()
+            .flat_map(|map| map.validate().into_iter().map(move |issue| (map, issue)))
+            .collect()
+    }
+
+    fn trace(&self, seed: usize) -> Trace<'_> {
+        let mut id = seed;
+        let mut steps = Vec::new();
+        for map in self.chain() {
+            let (to_id, range) = match map.find_range(id) {
+                Some((idx, to_id)) => (to_id, Some(idx)),
+                None => (id, None),
+            };
+            steps.push(Step {
+                map,
+                from_id: id,
+                to_id,
+                range,
+            });
+            id = to_id;
+        }
+        Trace { seed, steps }
+    }
+
+    #[cfg(test)]
     fn map_final(&self, id: usize) -> usize {
-        self.maps.iter().fold(id, |acc, m| m.map_from(acc))
+        self.chain().iter().fold(id, |acc, m| m.map_from(acc))
     }
 
-    fn min_loc(&self) -> usize {
-        self.seeds.iter().map(|s| self.map_final(*s)).min().unwrap()
+    // A single map from seed to location, equivalent to applying every map in
+    // turn.
+    fn composed(&self) -> Map {
+        let chain = self.chain();
+        let identity = Map::identity(&chain[0].from_name);
+        chain.iter().fold(identity, |acc, m| acc.compose(m))
     }
 
     fn min_loc_ranges(&self) -> usize {
-        let mapped_ranges = self.map_final_ranges();
-        mapped_ranges.iter().map(|r| r.start_id).min().unwrap()
+        self.map_final_ranges().min().unwrap()
     }
 
-    fn map_final_ranges(&self) -> Vec<IDRange> {
-        let ranges_in = self.seeds_as_ranges();
-        let mut ranges_in = Box::new(ranges_in);
+    fn map_final_ranges(&self) -> IntervalSet {
+        self.stages().pop().unwrap().1
+    }
+
+    // The IDs the seed ranges reach in each category from seed to location.
+    // Each stage is merged into as few ranges as possible before the next.
+    fn stages(&self) -> Vec<(&str, IntervalSet)> {
+        let chain = self.chain();
+        let mut stages = vec![(chain[0].from_name.as_str(), self.seeds_as_ranges())];
+        for map in chain {
+            let ids = map.map_ranges(&stages.last().unwrap().1);
+            stages.push((map.to_name.as_str(), ids));
+        }
+        stages
+    }
+
+    // Every ID in the first category that ends up as `id` in the last.
+    fn unmap_final(&self, id: usize) -> Vec<usize> {
+        let mut ids = vec![id];
+        for map in self.chain().iter().rev() {
+            ids = ids.iter().flat_map(|id| map.unmap(*id)).collect();
+        }
+        ids.sort();
+        ids
+    }
+
+    fn unmap_final_ranges(&self, ids: &IntervalSet) -> IntervalSet {
+        self.chain()
+            .iter()
+            .rev()
+            .fold(ids.clone(), |ids, map| map.unmap_ranges(&ids))
+    }
+
+    // The parts of the seed ranges that end up in the lowest fragment of
+    // locations the seed ranges reach. Fragments are the pieces each seed range
+    // is split into by the map ranges it crosses, never merged back together.
+    fn lowest_band_seeds(&self) -> IntervalSet {
+        let mut fragments: Vec<Range<usize>> = match &self.seeds {
+            Seeds::Ids(ids) => ids.iter().map(|id| *id..id + 1).collect(),
+            Seeds::Ranges(ranges) => ranges.clone(),
+        };
+        for map in self.chain() {
+            fragments = fragments
+                .iter()
+                .flat_map(|r| map.split(&IntervalSet::from(r.clone())))
+                .map(|(piece, to_id)| to_id..to_id + piece.len())
+                .collect();
+        }
+        let lowest = fragments.into_iter().min_by_key(|r| r.start);
+        match lowest {
+            Some(lowest) => self
+                .unmap_final_ranges(&IntervalSet::from(lowest))
+                .intersection(&self.seeds_as_ranges()),
+            None => IntervalSet::new(),
+        }
+    }
+
+    fn seeds_as_ranges(&self) -> IntervalSet {
+        self.seeds.as_ranges()
+    }
+}
+
+impl Seeds {
+    // Just the seeds from an almanac, as from_file or from_json would read them
+    fn load(text: &str, json: bool, mode: SeedMode) -> Result<Seeds, AlmanacError> {
+        let seeds = if json {
+            #[derive(Deserialize)]
+            struct SeedsOnly {
+                seeds: Seeds,
+            }
+            let only: SeedsOnly =
+                serde_json::from_str(text).map_err(|e| AlmanacError::Json(e.to_string()))?;
+            only.seeds
+        } else {
+            let first = text.lines().next().ok_or(AlmanacError::BadLine(1))?;
+            let numbers = parse_seeds_line(first).ok_or(AlmanacError::BadLine(1))?;
+            Seeds::new(numbers, mode)?
+        };
+        seeds.check()?;
+        Ok(seeds)
+    }
+
+    // Whether there are any seeds, and all of them fit in an IntervalSet
+    fn check(&self) -> Result<(), AlmanacError> {
+        if let Seeds::Ids(ids) = self {
+            if let Some(idx) = ids.iter().position(|id| *id == usize::MAX) {
+                return Err(AlmanacError::SeedIdOverflow(idx + 1));
+            }
+        }
+        if self.as_ranges().ranges().is_empty() {
+            return Err(AlmanacError::NoSeeds);
+        }
+        Ok(())
+    }
+
+    fn as_ranges(&self) -> IntervalSet {
+        match self {
+            Seeds::Ids(ids) => IntervalSet::from_ranges(ids.iter().map(|id| *id..id + 1)),
+            Seeds::Ranges(ranges) => IntervalSet::from_ranges(ranges.iter().cloned()),
+        }
+    }
+
+    fn min_loc(&self, composed: &Map) -> usize {
+        match self {
+            Seeds::Ids(ids) => ids.iter().map(|s| composed.lookup(*s)).min(),
+            // Whole ranges at a time, split by the composed map's ranges
+            Seeds::Ranges(_) => composed.map_ranges(&self.as_ranges()).min(),
+        }
+        .unwrap()
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Seeds::Ids(_) => "IDs",
+            Seeds::Ranges(_) => "ranges",
+        }
+    }
+
+    fn new(numbers: Vec<usize>, mode: SeedMode) -> Result<Seeds, AlmanacError> {
+        match mode {
+            SeedMode::Ids => Ok(Seeds::Ids(numbers)),
+            SeedMode::Ranges => {
+                if !numbers.len().is_multiple_of(2) {
+                    return Err(AlmanacError::OddSeedCount(numbers.len()));
+                }
+                let ranges = numbers
+                    .chunks(2)
+                    .enumerate()
+                    .map(|(idx, pair)| match pair[0].checked_add(pair[1]) {
+                        Some(end) => Ok(pair[0]..end),
+                        None => Err(AlmanacError::SeedRangeOverflow(idx + 1)),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(Seeds::Ranges(ranges))
+            }
+        }
+    }
+}
+
+// The numbers as on the seeds line
+impl fmt::Display for Seeds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let numbers: Vec<String> = match self {
+            Seeds::Ids(ids) => ids.iter().map(|id| id.to_string()).collect(),
+            Seeds::Ranges(ranges) => ranges
+                .iter()
+                .map(|r| format!("{} {}", r.start, r.len()))
+                .collect(),
+        };
+        write!(f, "{}", numbers.join(" "))
+    }
+}
+
+// The almanac as it would be written in the puzzle input
+impl fmt::Display for Almanac {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "seeds: {}", self.seeds)?;
         for map in self.maps.iter() {
-            let mapped_ranges = map.find_map_ranges(&ranges_in);
-            *ranges_in = mapped_ranges;
+            writeln!(f)?;
+            write!(f, "{map}")?;
         }
-        *ranges_in
+        Ok(())
     }
+}
 
-    fn seeds_as_ranges(&self) -> Vec<IDRange> {
-        // Interpret seeds as a list of pairs: (start_id, length)
-        let mut ranges = Vec::new();
-        for pair in self.seeds[..].chunks(2) {
-            ranges.push(IDRange::new(0, pair[0], pair[1]));
+impl fmt::Display for AlmanacError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlmanacError::BadLine(line) => write!(f, "line {line}: not part of an almanac"),
+            AlmanacError::Json(e) => write!(f, "not a JSON almanac: {e}"),
+            AlmanacError::Route(e) => write!(f, "{e}"),
+            AlmanacError::OddSeedCount(count) => write!(
+                f,
+                "line 1: {count} seed numbers can't be read as start and length pairs; \
+                 try --seeds ids"
+            ),
+            AlmanacError::NotSeedToLocation { from, to } => {
+                write!(f, "a {from}-to-{to} map, not seed-to-location")
+            }
+            AlmanacError::BadComposedMap(issue) => write!(f, "composed map: {issue}"),
+            AlmanacError::SeedIdOverflow(seed) => write!(f, "line 1: seed {seed} is too large"),
+            AlmanacError::NoSeeds => write!(f, "no seeds to find the closest location for"),
+            AlmanacError::SeedRangeOverflow(range) => {
+                write!(f, "line 1: seed range {range} runs past the largest ID")
+            }
         }
-        ranges
     }
 }
 
@@ -113,7 +686,7 @@ fn parse_seeds_line(line: &str) -> Option<Vec<usize>> {
         static ref SEED_LINE_RE: Regex = Regex::new(r"^seeds: *([\d ]+)$").unwrap();
     }
     let m = SEED_LINE_RE.captures(line)?;
-    Some(utils::parse_numbers_from_str(m.get(1).unwrap().as_str()))
+    utils::parse_numbers_from_str(m.get(1).unwrap().as_str())
 }
 
 impl Map {
@@ -129,54 +702,316 @@ impl Map {
         })
     }
 
+    // A composed seed-to-location map, as saved by save(). Its ranges are
+    // sorted by source ID so lookup() works, which needs them not to overlap.
+    fn load(text: &str, json: bool) -> Result<Map, AlmanacError> {
+        let mut map = if json {
+            serde_json::from_str(text).map_err(|e| AlmanacError::Json(e.to_string()))?
+        } else {
+            let mut lines = text.lines().enumerate().map(|(idx, l)| (idx + 1, l.trim()));
+            let (_, header) = lines.next().ok_or(AlmanacError::BadLine(1))?;
+            let mut map = Map::from_str(header).ok_or(AlmanacError::BadLine(1))?;
+            for (line_num, line) in lines.filter(|(_, l)| !l.is_empty()) {
+                let range = MapRange::from_str(line).ok_or(AlmanacError::BadLine(line_num))?;
+                map.ranges.push(range);
+            }
+            map
+        };
+        if map.from_name != "seed" || map.to_name != "location" {
+            return Err(AlmanacError::NotSeedToLocation {
+                from: map.from_name,
+                to: map.to_name,
+            });
+        }
+        // Overlapping destinations are fine; several seeds can share a location
+        let bad_issue = map.validate().into_iter().find(|issue| {
+            issue.is_overflow() || matches!(issue, MapIssue::OverlappingSources(_, _))
+        });
+        if let Some(issue) = bad_issue {
+            return Err(AlmanacError::BadComposedMap(issue));
+        }
+        map.ranges.sort_by_key(|r| r.source_id);
+        Ok(map)
+    }
+
+    fn save(&self, json: bool) -> String {
+        if json {
+            serde_json::to_string_pretty(self).unwrap()
+        } else {
+            self.to_string()
+        }
+    }
+
+    fn identity(name: &str) -> Map {
+        Map {
+            from_name: name.to_owned(),
+            to_name: name.to_owned(),
+            ranges: Vec::new(),
+        }
+    }
+
+    fn validate(&self) -> Vec<MapIssue> {
+        let mut issues = Vec::new();
+        for (idx, range) in self.ranges.iter().enumerate() {
+            let number = idx + 1;
+            if range.range_length == 0 {
+                issues.push(MapIssue::ZeroLength(number));
+            }
+            if range.source_id.checked_add(range.range_length).is_none() {
+                issues.push(MapIssue::SourceOverflow(number));
+            }
+            if range.dest_id.checked_add(range.range_length).is_none() {
+                issues.push(MapIssue::DestinationOverflow(number));
+            }
+            for (other_idx, other) in self.ranges[..idx].iter().enumerate() {
+                let other_number = other_idx + 1;
+                if overlap(other.source_id, range.source_id, other, range) {
+                    issues.push(MapIssue::OverlappingSources(other_number, number));
+                }
+                if overlap(other.dest_id, range.dest_id, other, range) {
+                    issues.push(MapIssue::OverlappingDestinations(other_number, number));
+                }
+            }
+        }
+        issues
+    }
+
     fn map_from(&self, from_id: usize) -> usize {
-        for map in self.ranges.iter() {
-            if let Some(to_id) = map.map_from(from_id) {
+        match self.find_range(from_id) {
+            Some((_, to_id)) => to_id,
+            None => from_id,
+        }
+    }
+
+    // The index of the first range that has from_id, and what it maps it to
+    fn find_range(&self, from_id: usize) -> Option<(usize, usize)> {
+        self.ranges
+            .iter()
+            .enumerate()
+            .find_map(|(idx, range)| Some((idx, range.map_from(from_id)?)))
+    }
+
+    // Like map_from, but by binary search. Only for maps whose ranges are sorted
+    // by source ID and don't overlap, as compose() leaves them.
+    fn lookup(&self, from_id: usize) -> usize {
+        let idx = self.ranges.partition_point(|r| r.source_id <= from_id);
+        if idx > 0 {
+            if let Some(to_id) = self.ranges[idx - 1].map_from(from_id) {
                 return to_id;
             }
         }
         from_id
     }
 
-    fn find_map_ranges(&self, ranges: &[IDRange]) -> Vec<IDRange> {
-        let mut mapped_ranges = Vec::new();
-        let mut unmapped_ranges = ranges.to_vec(); // copy
-        let this_level = ranges[0].map_level;
-        let mut new_ranges = Vec::new();
-        for map in self.ranges.iter() {
-            // Loop over this Map's MapRange's
-            new_ranges.clear();
-            for id_range in unmapped_ranges.iter() {
-                // Loop over input or leftover, unmapped ranges
-                let potential_ranges = map.map_range(&id_range);
-                for potential in potential_ranges {
-                    // Loop over the up-to-3 results
-                    if let Some(id_range) = potential {
-                        if id_range.map_level > this_level {
-                            // Taken care of by this mapping, so it's all done.
-                            mapped_ranges.push(id_range);
-                        } else {
-                            // Need to try next level
-                            new_ranges.push(id_range);
-                        }
+    // Splits the IDs into pieces by which of this map's ranges, if any, each
+    // falls in. Returns each piece with the ID its start maps to, in order.
+    fn split(&self, ids: &IntervalSet) -> Vec<(Range<usize>, usize)> {
+        let mut pieces = Vec::new();
+        let mut unmapped = ids.clone();
+        for range in self.ranges.iter() {
+            let (hit, missed) = range.map_range(&unmapped);
+            // Every ID the range takes moves the same way, so the pieces it
+            // took line up one for one with where they went
+            let taken = unmapped.difference(&missed);
+            let starts = hit.ranges().iter().map(|r| r.start);
+            pieces.extend(taken.ranges().iter().cloned().zip(starts));
+            unmapped = missed;
+        }
+        pieces.extend(unmapped.ranges().iter().map(|r| (r.clone(), r.start)));
+        pieces.sort_by_key(|(piece, _)| piece.start);
+        pieces
+    }
+
+    // The map equivalent to applying this one and then `next`. Its ranges are
+    // sorted and don't overlap; IDs that end up where they started are left to
+    // the identity default, and neighboring ranges are merged.
+    fn compose(&self, next: &Map) -> Map {
+        let mut ranges: Vec<MapRange> = Vec::new();
+        for (piece, mapped) in self.split(&IntervalSet::from(0..usize::MAX)) {
+            let mapped_ids = IntervalSet::from(mapped..mapped + piece.len());
+            for (next_piece, next_mapped) in next.split(&mapped_ids) {
+                let next_length = next_piece.len();
+                let source_id = piece.start + (next_piece.start - mapped);
+                if next_mapped == source_id {
+                    continue;
+                }
+                if let Some(last) = ranges.last_mut() {
+                    if last.source_id + last.range_length == source_id
+                        && last.dest_id + last.range_length == next_mapped
+                    {
+                        last.range_length += next_length;
+                        continue;
                     }
                 }
+                ranges.push(MapRange {
+                    dest_id: next_mapped,
+                    source_id,
+                    range_length: next_length,
+                });
+            }
+        }
+        Map {
+            from_name: self.from_name.clone(),
+            to_name: next.to_name.clone(),
+            ranges,
+        }
+    }
+
+    // All IDs that this map sends to `to_id`.
+    fn unmap(&self, to_id: usize) -> Vec<usize> {
+        let mut from_ids = Vec::new();
+        for (idx, range) in self.ranges.iter().enumerate() {
+            if let Some(from_id) = range.unmap(to_id) {
+                // Unless an earlier range takes it somewhere else first
+                let earlier = &self.ranges[..idx];
+                if earlier.iter().all(|r| r.map_from(from_id).is_none()) {
+                    from_ids.push(from_id);
+                }
+            }
+        }
+        if self.ranges.iter().all(|r| r.map_from(to_id).is_none()) {
+            from_ids.push(to_id); // mapped to itself by default
+        }
+        from_ids.sort();
+        from_ids
+    }
+
+    // All the IDs that these IDs map to. Each range gets whatever IDs are still
+    // unmapped by the ranges before it, and the rest map to themselves.
+    fn map_ranges(&self, ids: &IntervalSet) -> IntervalSet {
+        IntervalSet::from_ranges(
+            self.split(ids)
+                .into_iter()
+                .map(|(piece, to_id)| to_id..to_id + piece.len()),
+        )
+    }
+
+    // The inverse of map_ranges: all the IDs that map into these IDs.
+    fn unmap_ranges(&self, ids: &IntervalSet) -> IntervalSet {
+        let mut claimed = IntervalSet::new();
+        let mut unmapped = IntervalSet::new();
+        for range in self.ranges.iter() {
+            let sources = ids
+                .intersection(&IntervalSet::from(range.dest()))
+                .translate(range.dest_id, range.source_id)
+                .difference(&claimed);
+            unmapped = unmapped.union(&sources);
+            claimed = claimed.union(&IntervalSet::from(range.source()));
+        }
+        unmapped.union(&ids.difference(&claimed))
+    }
+}
+
+// The whole path on one line, then each step with how it was made
+impl fmt::Display for Trace<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut path = Vec::new();
+        if let Some(first) = self.steps.first() {
+            path.push(format!("{} {}", first.map.from_name, self.seed));
+        }
+        for step in self.steps.iter() {
+            path.push(format!("{} {}", step.map.to_name, step.to_id));
+        }
+        writeln!(f, "{}", path.join(" -> "))?;
+        for step in self.steps.iter() {
+            write!(
+                f,
+                "  {}-to-{}: {} -> {}",
+                step.map.from_name, step.map.to_name, step.from_id, step.to_id
+            )?;
+            match step.range {
+                Some(idx) => {
+                    let range = &step.map.ranges[idx];
+                    writeln!(
+                        f,
+                        " by range {} ({} {} {})",
+                        idx + 1,
+                        range.dest_id,
+                        range.source_id,
+                        range.range_length
+                    )?;
+                }
+                None => writeln!(f, " by default")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Whether two ranges starting at a_start and b_start (of their source or
+// destination IDs) share any IDs. Ends are saturated so that this works for
+// ranges that overflow too.
+fn overlap(a_start: usize, b_start: usize, a: &MapRange, b: &MapRange) -> bool {
+    let a_end = a_start.saturating_add(a.range_length);
+    let b_end = b_start.saturating_add(b.range_length);
+    a_start.max(b_start) < a_end.min(b_end)
+}
+
+impl MapIssue {
+    // Overflowing ranges can't be used at all
+    fn is_overflow(&self) -> bool {
+        matches!(
+            self,
+            MapIssue::SourceOverflow(_) | MapIssue::DestinationOverflow(_)
+        )
+    }
+}
+
+impl fmt::Display for MapIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapIssue::OverlappingSources(a, b) => write!(
+                f,
+                "ranges {a} and {b} overlap in source IDs, so range {a} wins"
+            ),
+            MapIssue::OverlappingDestinations(a, b) => write!(
+                f,
+                "ranges {a} and {b} overlap in destination IDs, so some IDs map to the same ID"
+            ),
+            MapIssue::ZeroLength(a) => write!(f, "range {a} has zero length"),
+            MapIssue::SourceOverflow(a) => write!(f, "range {a} runs past the largest source ID"),
+            MapIssue::DestinationOverflow(a) => {
+                write!(f, "range {a} runs past the largest destination ID")
+            }
+        }
+    }
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RouteError::UnknownCategory(name) => write!(f, "no maps to or from {name}"),
+            RouteError::NoRoute { from, to } => write!(f, "no way to map {from} to {to}"),
+            RouteError::Ambiguous { from, to, routes } => {
+                let routes: Vec<String> = routes.iter().map(|r| r.join(" -> ")).collect();
+                write!(
+                    f,
+                    "more than one way to map {from} to {to}: {}",
+                    routes.join(", ")
+                )
             }
-            // Next mapping gets all our would-be-defaulted ranges.
-            mem::swap(&mut new_ranges, &mut unmapped_ranges);
         }
-        // Every range still-unmapped defaults to original IDs
-        for mut unmapped_range in unmapped_ranges {
-            unmapped_range.map_level += 1;
-            mapped_ranges.push(unmapped_range);
+    }
+}
+
+// Written out the same way as in the almanac
+impl fmt::Display for Map {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}-to-{} map:", self.from_name, self.to_name)?;
+        for range in self.ranges.iter() {
+            writeln!(
+                f,
+                "{} {} {}",
+                range.dest_id, range.source_id, range.range_length
+            )?;
         }
-        mapped_ranges
+        Ok(())
     }
 }
 
 impl MapRange {
     fn from_str(s: &str) -> Option<MapRange> {
-        let nums = utils::parse_numbers_from_str(s);
+        let nums = utils::parse_numbers_from_str(s)?;
         if nums.len() != 3 {
             None
         } else {
@@ -200,102 +1035,43 @@ impl MapRange {
         }
     }
 
-    fn last_source_id(&self) -> usize {
-        self.source_id + self.range_length - 1
-    }
-
-    fn map_range(&self, source_range: &IDRange) -> [Option<IDRange>; 3] {
-        // This takes a (contiguous) source ID range and maps it into up-to three ranges.
-        // Parts of the source range outside the mapped range will retain a map_level equal
-        // to the given source_range's level to indicate that no explicit mapping occured.
-        // That part of the source range within the mapping interval corresponds to
-        // the (second) element of the output being mapped with incremented map_level.
-
-        // Note to self: this approach feels too complicated to me. I'm still struggling
-        // to find a way to simplify it.
-
-        let mut mapped = [None; 3];
-        if (source_range.last() < self.source_id) || (source_range.start_id > self.last_source_id())
-        {
-            // Easy -- source range is entirely outside our mapping range
-            mapped[0] = Some(source_range.clone());
-            return mapped;
-            // It's nice to take care of this case early so that later on we can assume
-            // that some part of source_range is explicitly mapped.
+    fn unmap(&self, to_id: usize) -> Option<usize> {
+        if to_id < self.dest_id {
+            return None;
         }
-
-        let source_range = if source_range.start_id < self.source_id {
-            // Some of source_range is before the mapping.
-            let head_length = self.source_id - source_range.start_id;
-            mapped[0] = Some(IDRange {
-                map_level: source_range.map_level, // not explicitly mapped
-                start_id: source_range.start_id,
-                length: head_length,
-            });
-            IDRange {
-                map_level: source_range.map_level,
-                start_id: self.source_id,
-                length: source_range.length - head_length,
-            }
-        } else {
-            source_range.clone()
-        };
-        if source_range.last() > self.last_source_id() {
-            // Source range extends beyond this mapping.
-            mapped[1] = Some(IDRange {
-                map_level: source_range.map_level + 1, // Explicitly-mapped region -- advanced map_level to indicate this.
-                start_id: self.map_from(source_range.start_id).unwrap(),
-                length: self.last_source_id() - source_range.start_id + 1,
-            });
-            mapped[2] = Some(IDRange {
-                map_level: source_range.map_level, // not explicitly mapped
-                start_id: self.last_source_id() + 1,
-                length: source_range.last() - self.last_source_id(),
-            });
+        let pos = to_id - self.dest_id;
+        if pos >= self.range_length {
+            None
         } else {
-            // Source range lies within this mapping.
-            mapped[1] = Some(IDRange {
-                map_level: source_range.map_level + 1, // Explicitly-mapped region -- advanced map_level to indicate this.
-                start_id: self.map_from(source_range.start_id).unwrap(),
-                length: source_range.length,
-            });
+            Some(self.source_id + pos)
         }
-        mapped
     }
-}
 
-impl IDRange {
-    fn new(map_level: usize, start_id: usize, length: usize) -> IDRange {
-        IDRange {
-            map_level,
-            start_id,
-            length,
-        }
-    }
-
-    //     fn map_and_subdivide(&mut self, maps: &[Map]) {
-    //         if self.map_level >= maps.len() {
-    //             return;
-    //         }
-    //         let map = &maps[self.map_level];
-    //         let mut subranges = Vec::new();
-    //
-    //         subranges.extend(map.find_map_ranges(&self).iter());
-    //
-    //         for subrange in subranges {
-    //             subrange.map_and_subdivide(maps);
-    //         }
-    //         self.sub_ranges = Some(subranges);
-    //     }
-
-    fn last(&self) -> usize {
-        self.start_id + self.length - 1
+    fn source(&self) -> Range<usize> {
+        self.source_id..self.source_id + self.range_length
+    }
+
+    fn dest(&self) -> Range<usize> {
+        self.dest_id..self.dest_id + self.range_length
+    }
+
+    // Splits the IDs into those in this range, which are returned mapped, and
+    // those that aren't.
+    fn map_range(&self, ids: &IntervalSet) -> (IntervalSet, IntervalSet) {
+        let source = IntervalSet::from(self.source());
+        (
+            ids.intersection(&source)
+                .translate(self.source_id, self.dest_id),
+            ids.difference(&source),
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
     use std::iter::zip;
 
     const EXAMPLE_1: &str = r#"seeds: 79 14 55 13
@@ -333,13 +1109,88 @@ mod tests {
     56 93 4"#;
 
     fn parse_example() -> Almanac {
-        Almanac::from_file(EXAMPLE_1.lines()).unwrap()
+        Almanac::from_file(EXAMPLE_1.lines(), SeedMode::Ranges).unwrap()
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        for mode in [SeedMode::Ids, SeedMode::Ranges] {
+            let a = Almanac::from_file(EXAMPLE_1.lines(), mode).unwrap();
+            let text = a.save(false);
+            assert!(text.starts_with("seeds: 79 14 55 13\n\nseed-to-soil map:\n50 98 2\n"));
+            let b = Almanac::from_file(text.lines(), mode).unwrap();
+            assert_eq!(b.save(false), text);
+
+            let json = a.save(true);
+            let c = Almanac::from_json(&json).unwrap();
+            assert_eq!(c.seeds, a.seeds);
+            assert_eq!(c.save(false), text);
+            assert_eq!(c.min_loc_ranges(), a.min_loc_ranges());
+        }
+        let json = parse_example().save(true);
+        assert!(json.contains("\"ranges\""));
+        assert!(matches!(
+            Almanac::from_json("{\"seeds\": 3}"),
+            Err(AlmanacError::Json(_))
+        ));
+
+        let composed = parse_example().composed();
+        for json in [false, true] {
+            let loaded = Map::load(&composed.save(json), json).unwrap();
+            assert_eq!(loaded.to_string(), composed.to_string());
+            assert_eq!(loaded.lookup(79), 82);
+        }
+        // With a composed map, the rest of the almanac isn't needed
+        let seeds_only = "seeds: 79 14 55 13\n\nnot a map\n";
+        let seeds = |mode| Seeds::load(seeds_only, false, mode).unwrap();
+        assert_eq!(seeds(SeedMode::Ids).min_loc(&composed), 35);
+        assert_eq!(seeds(SeedMode::Ranges).min_loc(&composed), 46);
+        let seeds = Seeds::load(&json, true, SeedMode::Ids).unwrap();
+        assert_eq!(seeds.min_loc(&composed), 46);
+        assert_eq!(
+            Seeds::load("seeds: 79 0\n", false, SeedMode::Ranges),
+            Err(AlmanacError::NoSeeds)
+        );
+        assert_eq!(
+            Map::load("seed-to-location map:\n1 2 3\n\n4 5\n", false).unwrap_err(),
+            AlmanacError::BadLine(4)
+        );
+
+        // Ranges in any order are sorted so lookup() can find them
+        let unsorted = Map {
+            from_name: "seed".to_owned(),
+            to_name: "location".to_owned(),
+            ranges: ["500 50 10", "100 10 5"]
+                .iter()
+                .map(|l| MapRange::from_str(l).unwrap())
+                .collect(),
+        };
+        for json in [false, true] {
+            let text = unsorted.save(json);
+            let map = Map::load(&text, json).unwrap();
+            assert_eq!(map.lookup(55), 505);
+            assert_eq!(map.lookup(12), 102);
+            assert_eq!(map.lookup(30), 30);
+        }
+        assert_eq!(
+            Map::load("seed-to-location map:\n500 50 10\n100 55 5\n", false).unwrap_err(),
+            AlmanacError::BadComposedMap(MapIssue::OverlappingSources(1, 2))
+        );
+        for line in ["foo bar baz", "50 98 x", "50 98 99999999999999999999999"] {
+            let text = format!("seed-to-location map:\n1 2 3\n{line}\n");
+            assert_eq!(
+                Map::load(&text, false).unwrap_err(),
+                AlmanacError::BadLine(3)
+            );
+        }
+        let err = Map::load("seed-to-soil map:\n500 50 10\n", false).unwrap_err();
+        assert_eq!(err.to_string(), "a seed-to-soil map, not seed-to-location");
     }
 
     #[test]
     fn test_parse_example() {
         let a = parse_example();
-        assert_eq!(a.seeds.len(), 4);
+        assert_eq!(a.seeds, Seeds::Ranges(vec![79..93, 55..68]));
         assert_eq!(a.maps.len(), 7);
         assert_eq!(a.maps[0].from_name, "seed");
         assert_eq!(a.maps[6].to_name, "location");
@@ -347,99 +1198,318 @@ mod tests {
 
     #[test]
     fn test_map_final() {
-        let a = parse_example();
-        for (seed_id, expected) in zip(&a.seeds, vec![82, 43, 86, 35]) {
-            let loc = a.map_final(*seed_id);
+        let a = Almanac::from_file(EXAMPLE_1.lines(), SeedMode::Ids).unwrap();
+        assert_eq!(a.seeds, Seeds::Ids(vec![79, 14, 55, 13]));
+        for (seed_id, expected) in zip([79, 14, 55, 13], vec![82, 43, 86, 35]) {
+            let loc = a.map_final(seed_id);
             assert_eq!(loc, expected);
         }
-        assert_eq!(a.min_loc(), 35);
+        assert_eq!(a.seeds.min_loc(&a.composed()), 35);
+    }
+
+    #[test]
+    fn test_seed_modes() {
+        // Each way of reading the seeds works for both ways of finding the
+        // closest location
+        let ids = Almanac::from_file(EXAMPLE_1.lines(), SeedMode::Ids).unwrap();
+        assert_eq!(ids.min_loc_ranges(), 35);
+        let ranges = parse_example();
+        assert_eq!(ranges.seeds.min_loc(&ranges.composed()), 46);
+
+        let odd = EXAMPLE_1.replacen("13", "13 7", 1);
+        assert_eq!(
+            Almanac::from_file(odd.lines(), SeedMode::Ranges).unwrap_err(),
+            AlmanacError::OddSeedCount(5)
+        );
+        let ids = Almanac::from_file(odd.lines(), SeedMode::Ids).unwrap();
+        assert_eq!(ids.min_loc_ranges(), ids.map_final(7).min(35));
+
+        let huge = EXAMPLE_1.replacen("55 13", &format!("55 {}", usize::MAX), 1);
+        let err = Almanac::from_file(huge.lines(), SeedMode::Ranges).unwrap_err();
+        assert_eq!(err, AlmanacError::SeedRangeOverflow(2));
+        assert_eq!(
+            err.to_string(),
+            "line 1: seed range 2 runs past the largest ID"
+        );
+        let err = Almanac::from_file(huge.lines(), SeedMode::Ids).unwrap_err();
+        assert_eq!(err.to_string(), "line 1: seed 4 is too large");
+
+        for seeds in ["seeds: ", "seeds: 79 0 55 0"] {
+            let text = EXAMPLE_1.replacen("seeds: 79 14 55 13", seeds, 1);
+            let err = Almanac::from_file(text.lines(), SeedMode::Ranges).unwrap_err();
+            assert_eq!(err, AlmanacError::NoSeeds);
+        }
+        let mut json: serde_json::Value =
+            serde_json::from_str(&parse_example().save(true)).unwrap();
+        json["seeds"] = serde_json::json!({ "ranges": [] });
+        let err = Almanac::from_json(&json.to_string()).unwrap_err();
+        assert_eq!(err, AlmanacError::NoSeeds);
+
+        // Ranges far too long to go through one seed at a time
+        let long = EXAMPLE_1.replacen("79 14 55 13", "79 14 55 100000000000", 1);
+        let a = Almanac::from_file(long.lines(), SeedMode::Ranges).unwrap();
+        assert_eq!(a.seeds.min_loc(&a.composed()), a.min_loc_ranges());
+
+        let bad = EXAMPLE_1.replacen("52 50 48", "52 50", 1);
+        let err = Almanac::from_file(bad.lines(), SeedMode::Ids).unwrap_err();
+        assert_eq!(err.to_string(), "line 5: not part of an almanac");
+        let bad = EXAMPLE_1.replacen("52 50 48", "52 50 x", 1);
+        let err = Almanac::from_file(bad.lines(), SeedMode::Ids).unwrap_err();
+        assert_eq!(err, AlmanacError::BadLine(5));
+        let bad = EXAMPLE_1.replacen("79 14", "79 99999999999999999999999", 1);
+        let err = Almanac::from_file(bad.lines(), SeedMode::Ids).unwrap_err();
+        assert_eq!(err, AlmanacError::BadLine(1));
     }
 
     #[test]
     fn check_map_range_map_range() {
         let mr = MapRange::from_str("100 10 5").unwrap();
-        let to_mapped = IDRange {
-            map_level: 1,
-            start_id: 1,
-            length: 2,
-        };
-        assert_eq!(mr.map_range(&to_mapped), [Some(to_mapped), None, None]);
-        let to_mapped = IDRange {
-            map_level: 1,
-            start_id: 15,
-            length: 2,
-        };
-        assert_eq!(mr.map_range(&to_mapped), [Some(to_mapped), None, None]);
+        let empty = IntervalSet::new();
+        let to_mapped = IntervalSet::from(1..3);
+        assert_eq!(mr.map_range(&to_mapped), (empty.clone(), to_mapped));
+        let to_mapped = IntervalSet::from(15..17);
+        assert_eq!(mr.map_range(&to_mapped), (empty.clone(), to_mapped));
 
-        let to_mapped = IDRange {
-            map_level: 1,
-            start_id: 5,
-            length: 15,
-        };
+        let to_mapped = IntervalSet::from(5..20);
         assert_eq!(
             mr.map_range(&to_mapped),
-            [
-                Some(IDRange {
-                    map_level: 1,
-                    start_id: 5,
-                    length: 5
-                }),
-                Some(IDRange {
-                    map_level: 2,
-                    start_id: 100,
-                    length: 5
-                }),
-                Some(IDRange {
-                    map_level: 1,
-                    start_id: 15,
-                    length: 5
-                }),
-            ]
+            (
+                IntervalSet::from(100..105),
+                IntervalSet::from_ranges([5..10, 15..20])
+            )
         );
 
-        let to_mapped = IDRange {
-            map_level: 1,
-            start_id: 5,
-            length: 8,
-        };
+        let to_mapped = IntervalSet::from(5..13);
         assert_eq!(
             mr.map_range(&to_mapped),
-            [
-                Some(IDRange {
-                    map_level: 1,
-                    start_id: 5,
-                    length: 5
-                }),
-                Some(IDRange {
-                    map_level: 2,
-                    start_id: 100,
-                    length: 3
-                }),
-                None,
-            ]
+            (IntervalSet::from(100..103), IntervalSet::from(5..10))
         );
 
-        let to_mapped = IDRange {
-            map_level: 1,
-            start_id: 12,
-            length: 8,
-        };
+        let to_mapped = IntervalSet::from(12..20);
         assert_eq!(
             mr.map_range(&to_mapped),
+            (IntervalSet::from(102..105), IntervalSet::from(15..20))
+        );
+
+        // Moving IDs further than isize reaches, both ways
+        let far = usize::MAX - 10;
+        let mr = MapRange::from_str(&format!("{far} 0 5")).unwrap();
+        let (mapped, _) = mr.map_range(&IntervalSet::from(1..3));
+        assert_eq!(mapped, IntervalSet::from(far + 1..far + 3));
+        let map = Map {
+            from_name: "a".to_owned(),
+            to_name: "b".to_owned(),
+            ranges: vec![mr],
+        };
+        // The IDs up there that no range moves map to themselves too
+        assert_eq!(
+            map.unmap_ranges(&mapped),
+            IntervalSet::from_ranges([1..3, far + 1..far + 3])
+        );
+    }
+
+    #[test]
+    fn test_composed() {
+        let a = parse_example();
+        let composed = a.composed();
+        assert_eq!(composed.from_name, "seed");
+        assert_eq!(composed.to_name, "location");
+        for id in 0..200 {
+            assert_eq!(composed.lookup(id), a.map_final(id));
+            assert_eq!(composed.map_from(id), a.map_final(id));
+        }
+        for r in composed.ranges.windows(2) {
+            assert!(r[0].source_id + r[0].range_length <= r[1].source_id);
+        }
+        assert_eq!(composed.lookup(usize::MAX - 1), usize::MAX - 1);
+
+        let text = composed.to_string();
+        assert!(text.starts_with("seed-to-location map:\n"));
+        assert_eq!(text.lines().count(), composed.ranges.len() + 1);
+    }
+
+    #[test]
+    fn test_unmap_final() {
+        let a = parse_example();
+        assert_eq!(a.unmap_final(82), [79]);
+        assert_eq!(a.unmap_final(35), [13]);
+        // Brute force over enough seeds to cover everything the maps move
+        let mut expected = vec![Vec::new(); 120];
+        for seed in 0..300 {
+            let loc = a.map_final(seed);
+            if loc < expected.len() {
+                expected[loc].push(seed);
+            }
+        }
+        for (loc, seeds) in expected.iter().enumerate() {
+            assert_eq!(&a.unmap_final(loc), seeds, "location {loc}");
+        }
+    }
+
+    #[test]
+    fn test_unmap_final_ranges() {
+        let a = parse_example();
+        let seed_ranges = a.unmap_final_ranges(&IntervalSet::from(40..70));
+        let seeds: Vec<usize> = seed_ranges.ranges().iter().cloned().flatten().collect();
+        let expected: Vec<usize> = (0..300)
+            .filter(|s| (40..70).contains(&a.map_final(*s)))
+            .collect();
+        assert_eq!(seeds, expected);
+
+        // Seed 82 is the one that reaches location 46
+        let lowest = a.lowest_band_seeds();
+        assert!(lowest.ranges().iter().any(|r| r.contains(&82)));
+        for seed in lowest.ranges().iter().cloned().flatten() {
+            assert!(a.map_final(seed) >= 46);
+        }
+        // Only the fragment through 46..56, not everything merged with it
+        assert_eq!(lowest, IntervalSet::from(82..92));
+
+        let mut a = parse_example();
+        a.seeds = Seeds::Ranges(Vec::new());
+        assert_eq!(a.lowest_band_seeds(), IntervalSet::new());
+    }
+
+    #[test]
+    fn test_unmap_overlapping() {
+        // Where ranges overlap, IDs only come from the first one that has them
+        let map = Map {
+            from_name: "a".to_owned(),
+            to_name: "b".to_owned(),
+            ranges: vec![
+                MapRange::from_str("100 10 5").unwrap(),
+                MapRange::from_str("0 12 10").unwrap(),
+            ],
+        };
+        assert_eq!(map.unmap(0), [0]); // not 12, which goes to 102
+        assert_eq!(map.unmap(3), [3, 15]);
+        assert_eq!(map.unmap(12), Vec::<usize>::new());
+        assert_eq!(map.unmap(101), [11, 101]);
+        let ranges = map.unmap_ranges(&IntervalSet::from(0..5));
+        assert_eq!(ranges.ranges(), [0..5, 15..17]);
+    }
+
+    // The example with its maps in a different order
+    fn shuffled_example() -> String {
+        let example: Vec<String> = EXAMPLE_1.lines().map(|l| l.trim().to_owned()).collect();
+        let example = example.join("\n");
+        let mut blocks: Vec<&str> = example.split("\n\n").collect();
+        let seeds = blocks.remove(0);
+        blocks.reverse();
+        blocks.swap(1, 4);
+        format!("{seeds}\n\n{}", blocks.join("\n\n"))
+    }
+
+    #[test]
+    fn test_route() {
+        let a = parse_example();
+        let route = a.route("soil", "light").unwrap();
+        let names: Vec<&str> = route.iter().map(|m| m.to_name.as_str()).collect();
+        assert_eq!(names, ["fertilizer", "water", "light"]);
+        assert!(a.route("water", "water").unwrap().is_empty());
+        assert_eq!(a.map_between("seed", "soil", 79), Ok(81));
+        assert_eq!(a.map_between("soil", "location", 81), Ok(82));
+        assert_eq!(a.map_between("light", "light", 5), Ok(5));
+
+        assert_eq!(
+            a.route("seed", "dirt").unwrap_err(),
+            RouteError::UnknownCategory("dirt".to_owned())
+        );
+        assert_eq!(
+            a.route("location", "seed").unwrap_err().to_string(),
+            "no way to map location to seed"
+        );
+    }
+
+    #[test]
+    fn test_route_ambiguous() {
+        let text = format!(
+            "{}\n\nlocation-to-mud map:\n1 2 3\n\nmud-to-clay map:\n\nlocation-to-clay map:\n",
+            shuffled_example()
+        );
+        let a = Almanac::from_file(text.lines(), SeedMode::Ranges).unwrap();
+        assert!(a.route("water", "location").is_ok());
+        assert_eq!(
+            a.route("location", "clay").unwrap_err().to_string(),
+            "more than one way to map location to clay: \
+             location -> mud -> clay, location -> clay"
+        );
+
+        // Seed to location has to be settled before anything else happens
+        let text = format!("{}\n\nseed-to-water map:\n1 2 3", shuffled_example());
+        let err = Almanac::from_file(text.lines(), SeedMode::Ranges).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "more than one way to map seed to location: \
+             seed -> soil -> fertilizer -> water -> light -> temperature -> humidity -> location, \
+             seed -> water -> light -> temperature -> humidity -> location"
+        );
+        let text = EXAMPLE_1.replacen("humidity-to-location", "humidity-to-place", 1);
+        let err = Almanac::from_file(text.lines(), SeedMode::Ranges).unwrap_err();
+        assert_eq!(
+            err,
+            AlmanacError::Route(RouteError::UnknownCategory("location".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let text = shuffled_example();
+        let a = Almanac::from_file(text.lines(), SeedMode::Ranges).unwrap();
+        assert_ne!(a.maps[0].from_name, "seed");
+        assert_eq!(a.seeds.min_loc(&a.composed()), 46);
+        assert_eq!(a.min_loc_ranges(), 46);
+        assert_eq!(a.unmap_final(82), [79]);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(parse_example().validate().is_empty());
+
+        let text = format!(
+            "seed-to-soil map:\n10 0 5\n0 3 4\n50 50 0\n12 20 2\n0 {} 2\n{} 30 1",
+            usize::MAX,
+            usize::MAX
+        );
+        let mut lines = text.lines();
+        let mut map = Map::from_str(lines.next().unwrap()).unwrap();
+        for line in lines {
+            map.ranges.push(MapRange::from_str(line).unwrap());
+        }
+        assert_eq!(
+            map.validate(),
             [
-                None,
-                Some(IDRange {
-                    map_level: 2,
-                    start_id: 102,
-                    length: 3
-                }),
-                Some(IDRange {
-                    map_level: 1,
-                    start_id: 15,
-                    length: 5
-                }),
+                MapIssue::OverlappingSources(1, 2),
+                MapIssue::ZeroLength(3),
+                MapIssue::OverlappingDestinations(1, 4),
+                MapIssue::SourceOverflow(5),
+                MapIssue::OverlappingDestinations(2, 5),
+                MapIssue::DestinationOverflow(6),
             ]
         );
+        assert_eq!(
+            MapIssue::OverlappingSources(1, 2).to_string(),
+            "ranges 1 and 2 overlap in source IDs, so range 1 wins"
+        );
+        assert!(MapIssue::SourceOverflow(5).is_overflow());
+        assert!(!MapIssue::ZeroLength(3).is_overflow());
+    }
+
+    #[test]
+    fn test_split() {
+        let map = Map {
+            from_name: "a".to_owned(),
+            to_name: "b".to_owned(),
+            ranges: vec![
+                MapRange::from_str("100 10 5").unwrap(),
+                MapRange::from_str("0 12 10").unwrap(),
+            ],
+        };
+        // The first range wins where they overlap
+        assert_eq!(
+            map.split(&IntervalSet::from(5..25)),
+            [(5..10, 5), (10..15, 100), (15..22, 3), (22..25, 22)]
+        );
     }
 
     #[test]
@@ -448,4 +1518,130 @@ mod tests {
         let min_loc = a.min_loc_ranges();
         assert_eq!(min_loc, 46);
     }
+
+    #[test]
+    fn test_trace() {
+        let a = parse_example();
+        let trace = a.trace(79);
+        assert_eq!(trace.steps.len(), 7);
+        assert_eq!(trace.steps[0].range, Some(1));
+        assert_eq!(trace.steps[1].range, None);
+        assert_eq!(trace.steps.last().unwrap().to_id, 82);
+        let text = trace.to_string();
+        let mut lines = text.lines();
+        assert_eq!(
+            lines.next(),
+            Some(
+                "seed 79 -> soil 81 -> fertilizer 81 -> water 81 -> light 74 \
+                 -> temperature 78 -> humidity 78 -> location 82"
+            )
+        );
+        assert_eq!(
+            lines.next(),
+            Some("  seed-to-soil: 79 -> 81 by range 2 (52 50 48)")
+        );
+        assert_eq!(
+            lines.next(),
+            Some("  soil-to-fertilizer: 81 -> 81 by default")
+        );
+        for seed in 0..100 {
+            assert_eq!(a.trace(seed).steps.last().unwrap().to_id, a.map_final(seed));
+        }
+    }
+
+    #[test]
+    fn test_stages() {
+        let a = parse_example();
+        let counts: Vec<(&str, usize)> = a
+            .stages()
+            .iter()
+            .map(|(category, ids)| (*category, ids.ranges().len()))
+            .collect();
+        assert_eq!(counts[0], ("seed", 2));
+        assert_eq!(counts[7].0, "location");
+        assert_eq!(a.stages()[7].1, a.map_final_ranges());
+
+        // Lots of tiny ranges that all move IDs the same way end up as one
+        // range again, however many times they're applied
+        let mut text = "seeds: 0 1000\n".to_owned();
+        let names = ["seed", "a", "b", "c", "location"];
+        for pair in names.windows(2) {
+            text.push_str(&format!("\n{}-to-{} map:\n", pair[0], pair[1]));
+            for id in (0..1000).rev() {
+                text.push_str(&format!("{} {id} 1\n", id + 5));
+            }
+        }
+        let a = Almanac::from_file(text.lines(), SeedMode::Ranges).unwrap();
+        for (_, ids) in a.stages() {
+            assert_eq!(ids.ranges().len(), 1);
+        }
+        assert_eq!(a.map_final_ranges(), IntervalSet::from(20..1005));
+    }
+
+    // A chain of a few maps from seed to location, with small and possibly
+    // overlapping ranges, and a few seed ranges
+    fn random_almanac(rng: &mut StdRng) -> Almanac {
+        let num_maps = rng.gen_range(1..5);
+        let mut names: Vec<String> = (1..num_maps).map(|n| format!("c{n}")).collect();
+        names.insert(0, "seed".to_owned());
+        names.push("location".to_owned());
+        let maps = names
+            .windows(2)
+            .map(|pair| Map {
+                from_name: pair[0].clone(),
+                to_name: pair[1].clone(),
+                ranges: (0..rng.gen_range(0..5))
+                    .map(|_| MapRange {
+                        dest_id: rng.gen_range(0..50),
+                        source_id: rng.gen_range(0..50),
+                        range_length: rng.gen_range(1..10),
+                    })
+                    .collect(),
+            })
+            .collect();
+        let seeds = Seeds::Ranges(
+            (0..rng.gen_range(1..4))
+                .map(|_| {
+                    let start = rng.gen_range(0..50);
+                    start..start + rng.gen_range(1..10)
+                })
+                .collect(),
+        );
+        Almanac::new(seeds, maps).unwrap()
+    }
+
+    #[test]
+    fn test_random_almanacs() {
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..500 {
+            let a = random_almanac(&mut rng);
+            // Nothing moves past 60, so 100 covers every ID that can move
+            let seeds = a.seeds_as_ranges();
+            let locations = a.map_final_ranges();
+            let expected = IntervalSet::from_ranges(
+                seeds
+                    .ranges()
+                    .iter()
+                    .cloned()
+                    .flatten()
+                    .map(|s| a.map_final(s)..a.map_final(s) + 1),
+            );
+            assert_eq!(locations, expected);
+            assert_eq!(a.min_loc_ranges(), expected.min().unwrap());
+
+            let start = rng.gen_range(0..70);
+            let targets = IntervalSet::from(start..start + rng.gen_range(1..20));
+            let expected = IntervalSet::from_ranges(
+                (0..100)
+                    .filter(|s| targets.ranges()[0].contains(&a.map_final(*s)))
+                    .map(|s| s..s + 1),
+            );
+            assert_eq!(a.unmap_final_ranges(&targets), expected);
+
+            let composed = a.composed();
+            for id in 0..100 {
+                assert_eq!(composed.lookup(id), a.map_final(id));
+            }
+        }
+    }
 }
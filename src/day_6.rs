@@ -1,36 +1,114 @@
+use num::integer::Roots;
+use num::{BigInt, Integer};
+use std::fmt::{Debug, Display};
 use std::fs::File;
 use std::io::{self, BufRead};
+use std::str::FromStr;
 
 const INPUT: &str = "inputs/input_6.txt";
 
-pub fn main() {
+#[derive(clap::Args)]
+pub struct Args {
+    /// Use arbitrarily large integers, for times and records that don't fit in 64 bits
+    #[arg(long)]
+    big: bool,
+}
+
+pub fn main(args: &Args) {
     let file = File::open(INPUT).unwrap();
     let lines: Vec<String> = io::BufReader::new(file).lines().take(2).flatten().collect();
-    let times = line_to_ints(&lines[0]);
-    let distances = line_to_ints(&lines[1]);
-    let ways: Vec<i64> = times
-        .iter()
-        .zip(distances.iter())
-        .map(|(t, d)| ways_to_beat(*t, *d))
-        .collect();
-    let ttl_ways: i64 = ways.iter().product();
+    if args.big {
+        solve::<BigInt>(&lines);
+    } else {
+        solve::<u64>(&lines);
+    }
+}
+
+fn solve<T>(lines: &[String])
+where
+    T: Int + Display + FromStr,
+    T::Err: Debug,
+{
+    let times: Vec<T> = line_to_ints(&lines[0]);
+    let distances: Vec<T> = line_to_ints(&lines[1]);
+    let ttl_ways = times
+        .into_iter()
+        .zip(distances)
+        .fold(T::one(), |acc, (t, d)| acc * ways_to_beat(t, d));
     println!("Part 1: {ttl_ways} total ways to beat all records");
 
-    let one_time = line_to_single_int(&lines[0]);
-    let one_dist = line_to_single_int(&lines[1]);
+    let one_time: T = line_to_single_int(&lines[0]);
+    let one_dist: T = line_to_single_int(&lines[1]);
     println!("Time: {one_time}, Record: {one_dist}");
     let ttl_ways = ways_to_beat(one_time, one_dist);
     println!("Part 2: {ttl_ways} total ways to win the one race.");
 }
 
-fn line_to_ints(line: &str) -> Vec<i64> {
+// What ways_to_beat works with: u64, u128, BigInt and so on. Distances go up
+// to t^2 / 4, so they're worked out in Wide, which has room for t^2.
+trait Int: Clone + Integer + Roots {
+    type Wide: Clone + Integer + Roots + From<Self>;
+
+    // Back from Wide, for answers no bigger than t + 1
+    fn narrow(wide: Self::Wide) -> Self;
+}
+
+impl Int for i32 {
+    type Wide = i64;
+
+    fn narrow(wide: i64) -> i32 {
+        i32::try_from(wide).expect("too many ways to fit in an i32")
+    }
+}
+
+impl Int for u64 {
+    type Wide = u128;
+
+    fn narrow(wide: u128) -> u64 {
+        u64::try_from(wide).expect("too many ways to fit in a u64")
+    }
+}
+
+impl Int for i64 {
+    type Wide = i128;
+
+    fn narrow(wide: i128) -> i64 {
+        i64::try_from(wide).expect("too many ways to fit in an i64")
+    }
+}
+
+impl Int for u128 {
+    type Wide = BigInt;
+
+    fn narrow(wide: BigInt) -> u128 {
+        u128::try_from(wide).expect("too many ways to fit in a u128")
+    }
+}
+
+impl Int for BigInt {
+    type Wide = BigInt;
+
+    fn narrow(wide: BigInt) -> BigInt {
+        wide
+    }
+}
+
+fn line_to_ints<T>(line: &str) -> Vec<T>
+where
+    T: FromStr,
+    T::Err: Debug,
+{
     line.split_whitespace()
         .skip(1)
         .map(|s| s.parse().unwrap())
         .collect()
 }
 
-fn line_to_single_int(line: &str) -> i64 {
+fn line_to_single_int<T>(line: &str) -> T
+where
+    T: FromStr,
+    T::Err: Debug,
+{
     let pieces: Vec<&str> = line.split_whitespace().skip(1).collect();
     pieces.join("").parse().unwrap()
 }
@@ -43,41 +121,46 @@ fn line_to_single_int(line: &str) -> i64 {
  * To beat the record r, $ct - c^2 > r$ which lies inside the interval...
  * (-b±√(b²-4ac))/(2a) with a -> 1, b -> -t, c -> r. So:
  * ( t ± √(t^2 - 4r) ) / 2.
+ *
+ * Floats lose precision once t^2 - 4r passes 2^53, so this is done in
+ * integers: the integer square root gets the lower bound to within a step or
+ * so, and checking the distance itself settles it exactly. The interval is
+ * symmetric about t/2, so the upper bound is t minus the lower one.
  */
 
-enum Bounds {
-    Impossible,
-    JustPossible(f64),
-    Within(f64, f64),
+fn ways_to_beat<T: Int>(duration: T, record: T) -> T {
+    T::narrow(count_ways(T::Wide::from(duration), T::Wide::from(record)))
 }
 
-fn times_to_match(t: f64, r: f64) -> Bounds {
-    let pms = t * t - 4.0 * r;
-    if pms < 0.0 {
-        return Bounds::Impossible;
-    } else if pms == 0.0 {
-        return Bounds::JustPossible(t / 2.0);
-    } else {
-        let pm = pms.sqrt();
-        return Bounds::Within((t - pm) / 2.0, (t + pm) / 2.0);
+// ways_to_beat in a type with room for duration squared
+fn count_ways<T: Clone + Integer + Roots>(duration: T, record: T) -> T {
+    let two = T::one() + T::one();
+    let beats = |c: &T| c.clone() * (duration.clone() - c.clone()) > record;
+    // The longest distance comes from charging for half the time
+    if !beats(&(duration.clone() / two.clone())) {
+        return T::zero();
     }
-}
-
-fn ways_to_beat(duration: i64, record: i64) -> i64 {
-    let ttm = times_to_match(duration as f64, record as f64);
-    match ttm {
-        Bounds::Impossible => 0,
-        Bounds::JustPossible(_) => 0,
-        Bounds::Within(low, high) => {
-            println!("Can beat {duration} ms with record {record} mm between {low} and {high}");
-            high.ceil() as i64 - low.floor() as i64 - 1
-        }
+    let discriminant =
+        duration.clone() * duration.clone() - two.clone() * two.clone() * record.clone();
+    let mut low = (duration.clone() - discriminant.sqrt()) / two;
+    if low < T::zero() {
+        low = T::zero();
+    }
+    while !beats(&low) {
+        low = low + T::one();
     }
+    while low > T::zero() && beats(&(low.clone() - T::one())) {
+        low = low - T::one();
+    }
+    let high = duration - low.clone();
+    high - low + T::one()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
 
     #[test]
     fn test_ways() {
@@ -88,12 +171,90 @@ mod tests {
 
     #[test]
     fn test_line_to_single() {
-        let lines = vec!["Time:      7  15   30", "Distance:  9  40  200"];
-        let one_time = line_to_single_int(&lines[0]);
-        let one_dist = line_to_single_int(&lines[1]);
+        let lines = ["Time:      7  15   30", "Distance:  9  40  200"];
+        let one_time: i64 = line_to_single_int(lines[0]);
+        let one_dist: i64 = line_to_single_int(lines[1]);
         let ttl_ways = ways_to_beat(one_time, one_dist);
         assert_eq!(one_time, 71530);
         assert_eq!(one_dist, 940200);
         assert_eq!(ttl_ways, 71503);
     }
+
+    fn brute_force(duration: u64, record: u64) -> u64 {
+        (0..=duration)
+            .filter(|c| c * (duration - c) > record)
+            .count() as u64
+    }
+
+    #[test]
+    fn test_random_races() {
+        let mut rng = StdRng::seed_from_u64(6);
+        for _ in 0..2000 {
+            let duration: u64 = rng.gen_range(0..300);
+            // Records up to a little past the best possible distance
+            let record = rng.gen_range(0..=duration * duration / 4 + 2);
+            let expected = brute_force(duration, record);
+            assert_eq!(ways_to_beat(duration, record), expected);
+            assert_eq!(
+                ways_to_beat(duration as u128, record as u128),
+                expected as u128
+            );
+            assert_eq!(
+                ways_to_beat(BigInt::from(duration), BigInt::from(record)),
+                BigInt::from(expected)
+            );
+        }
+        // Just beating, just tying and just missing the best distance
+        assert_eq!(ways_to_beat(10u64, 24), 1);
+        assert_eq!(ways_to_beat(10u64, 25), 0);
+        assert_eq!(ways_to_beat(11u64, 29), 2);
+        assert_eq!(ways_to_beat(11u64, 30), 0);
+        assert_eq!(ways_to_beat(0u64, 0), 0);
+    }
+
+    #[test]
+    fn test_large_races() {
+        // Well past where f64 can tell neighboring values of t^2 - 4r apart
+        let mut rng = StdRng::seed_from_u64(6);
+        for _ in 0..200 {
+            let duration: u128 = rng.gen_range(1 << 40..1 << 60);
+            let best = (duration / 2) * (duration - duration / 2);
+            let record = best - rng.gen_range(0..1 << 20);
+            let ways = ways_to_beat(duration, record);
+            let big = ways_to_beat(BigInt::from(duration), BigInt::from(record));
+            assert_eq!(big, BigInt::from(ways));
+            if ways > 0 {
+                // The bounds are exactly where the record stops being beaten
+                let low = (duration + 1 - ways) / 2;
+                assert!(low * (duration - low) > record);
+                assert!((low - 1) * (duration - low + 1) <= record);
+            }
+        }
+        let duration = BigInt::from(10).pow(30);
+        let record = BigInt::from(10).pow(59);
+        let ways = ways_to_beat(duration.clone(), record.clone());
+        let low: BigInt = (duration.clone() + 1 - ways) / 2;
+        assert!(low.clone() * (duration.clone() - low.clone()) > record);
+        assert!((low.clone() - 1) * (duration - low + 1) <= record);
+    }
+
+    #[test]
+    fn test_races_past_32_bits() {
+        // Where t^2 no longer fits in the type itself
+        let mut rng = StdRng::seed_from_u64(6);
+        for _ in 0..200 {
+            let duration: u64 = rng.gen_range(1 << 33..u64::MAX);
+            let record: u64 = rng.gen();
+            let ways = ways_to_beat(duration, record);
+            let (duration, record) = (duration as u128, record as u128);
+            assert_eq!(ways as u128, ways_to_beat(duration, record));
+            let low = (duration + 1 - ways as u128) / 2;
+            assert!(low * (duration - low) > record);
+            assert!((low - 1) * (duration - low + 1) <= record);
+        }
+        assert_eq!(ways_to_beat(1u64 << 34, 5), (1 << 34) - 1);
+        assert_eq!(ways_to_beat(u64::MAX, 0), u64::MAX - 1);
+        assert_eq!(ways_to_beat(u64::MAX, u64::MAX), u64::MAX - 3);
+        assert_eq!(ways_to_beat(u128::MAX, 0), u128::MAX - 1);
+    }
 }
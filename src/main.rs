@@ -23,11 +23,11 @@ struct Arguments {
 #[derive(Subcommand)]
 enum Op {
     Day1,
-    Day2,
-    Day3,
-    Day4,
-    Day5,
-    Day6,
+    Day2(day_2::Args),
+    Day3(day_3::Args),
+    Day4(day_4::Args),
+    Day5(day_5::Args),
+    Day6(day_6::Args),
     Day7,
     Day8,
 }
@@ -36,11 +36,11 @@ fn main() {
     let args = Arguments::parse();
     match args.operation {
         Op::Day1 => day_1::main(),
-        Op::Day2 => day_2::main(),
-        Op::Day3 => day_3::main(),
-        Op::Day4 => day_4::main(),
-        Op::Day5 => day_5::main(),
-        Op::Day6 => day_6::main(),
+        Op::Day2(args) => day_2::main(&args),
+        Op::Day3(args) => day_3::main(&args),
+        Op::Day4(args) => day_4::main(&args),
+        Op::Day5(args) => day_5::main(&args),
+        Op::Day6(args) => day_6::main(&args),
         Op::Day7 => day_7::main(),
         Op::Day8 => day_8::main(),
     }
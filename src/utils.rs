@@ -1,5 +1,6 @@
 use std::fs::File;
 use std::io::{self, BufRead};
+use std::ops::Range;
 
 pub fn lines_in_file(filename: &str) -> impl Iterator<Item = String> {
     let file = File::open(filename).unwrap();
@@ -8,26 +9,206 @@ pub fn lines_in_file(filename: &str) -> impl Iterator<Item = String> {
         .map(|l| l.expect("Bad line!"))
 }
 
-pub fn parse_numbers_from_str(s: &str) -> Vec<usize> {
+// None if any of them isn't a number or is too large for usize
+pub fn parse_numbers_from_str(s: &str) -> Option<Vec<usize>> {
     s.trim()
         .split(' ')
-        .filter_map(|s| {
-            if s.len() > 0 {
-                Some(s.parse().unwrap())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().ok())
+        .collect()
+}
+
+// A set of IDs, kept as sorted intervals that neither overlap nor touch.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IntervalSet {
+    intervals: Vec<Range<usize>>,
+}
+
+impl IntervalSet {
+    pub fn new() -> IntervalSet {
+        IntervalSet::default()
+    }
+
+    // Any ranges at all, which are sorted and merged where they overlap or touch
+    pub fn from_ranges<I>(ranges: I) -> IntervalSet
+    where
+        I: IntoIterator<Item = Range<usize>>,
+    {
+        let mut ranges: Vec<Range<usize>> = ranges.into_iter().filter(|r| !r.is_empty()).collect();
+        ranges.sort_by_key(|r| r.start);
+        let mut intervals: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match intervals.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => intervals.push(range),
+            }
+        }
+        IntervalSet { intervals }
+    }
+
+    pub fn ranges(&self) -> &[Range<usize>] {
+        &self.intervals
+    }
+
+    pub fn min(&self) -> Option<usize> {
+        self.intervals.first().map(|r| r.start)
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        IntervalSet::from_ranges(self.intervals.iter().chain(other.intervals.iter()).cloned())
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        let mut intervals = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.intervals.len() && j < other.intervals.len() {
+            let (a, b) = (&self.intervals[i], &other.intervals[j]);
+            let start = a.start.max(b.start);
+            let end = a.end.min(b.end);
+            if start < end {
+                intervals.push(start..end);
+            }
+            // Whichever ends first can't meet anything else
+            if a.end < b.end {
+                i += 1;
             } else {
-                None
+                j += 1;
             }
-        })
-        .collect()
+        }
+        IntervalSet { intervals }
+    }
+
+    pub fn difference(&self, other: &IntervalSet) -> IntervalSet {
+        let mut intervals = Vec::new();
+        let mut j = 0;
+        for range in self.intervals.iter() {
+            let mut start = range.start;
+            // Skip what ends before this range
+            while j < other.intervals.len() && other.intervals[j].end <= start {
+                j += 1;
+            }
+            let mut k = j;
+            while k < other.intervals.len() && other.intervals[k].start < range.end {
+                let cut = &other.intervals[k];
+                if cut.start > start {
+                    intervals.push(start..cut.start);
+                }
+                start = start.max(cut.end);
+                k += 1;
+            }
+            if start < range.end {
+                intervals.push(start..range.end);
+            }
+        }
+        IntervalSet { intervals }
+    }
+
+    // Every ID moved as far as `from` is from `to`. Panics if any would fall
+    // outside usize.
+    pub fn translate(&self, from: usize, to: usize) -> IntervalSet {
+        let shift = |id: usize| {
+            if to >= from {
+                id.checked_add(to - from)
+            } else {
+                id.checked_sub(from - to)
+            }
+            .expect("translated out of range")
+        };
+        IntervalSet {
+            intervals: self
+                .intervals
+                .iter()
+                .map(|r| shift(r.start)..shift(r.end))
+                .collect(),
+        }
+    }
+}
+
+impl From<Range<usize>> for IntervalSet {
+    fn from(range: Range<usize>) -> IntervalSet {
+        IntervalSet::from_ranges([range])
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
 
     #[test]
     fn test_numbers_from_str() {
         let numbers = parse_numbers_from_str(" 1 12  13   145 ");
-        assert_eq!(numbers, vec![1, 12, 13, 145]);
+        assert_eq!(numbers, Some(vec![1, 12, 13, 145]));
+        assert_eq!(parse_numbers_from_str("50 98 x"), None);
+        assert_eq!(parse_numbers_from_str("1 99999999999999999999999"), None);
+    }
+
+    #[test]
+    fn test_interval_set() {
+        let a = IntervalSet::from_ranges([5..8, 1..3, 3..4, 7..10, 12..12]);
+        assert_eq!(a.ranges(), [1..4, 5..10]);
+        assert_eq!(a.min(), Some(1));
+        let b = IntervalSet::from_ranges([0..2, 6..7, 9..20]);
+        assert_eq!(a.union(&b).ranges(), [0..4, 5..20]);
+        assert_eq!(a.intersection(&b).ranges(), [1..2, 6..7, 9..10]);
+        assert_eq!(a.difference(&b).ranges(), [2..4, 5..6, 7..9]);
+        assert_eq!(b.difference(&a).ranges(), [0..1, 10..20]);
+        assert_eq!(a.translate(1, 0).ranges(), [0..3, 4..9]);
+        assert_eq!(a.translate(0, 100).ranges(), [101..104, 105..110]);
+        // Further than isize reaches
+        let far = usize::MAX - 20;
+        assert_eq!(
+            a.translate(0, far).ranges(),
+            [far + 1..far + 4, far + 5..far + 10]
+        );
+        assert_eq!(
+            a.translate(0, far).translate(far, 0).ranges(),
+            [1..4, 5..10]
+        );
+        assert_eq!(IntervalSet::new().min(), None);
+        assert_eq!(IntervalSet::from(3..3), IntervalSet::new());
+    }
+
+    fn random_set(rng: &mut StdRng) -> (IntervalSet, Vec<bool>) {
+        let mut members = vec![false; 64];
+        let mut ranges = Vec::new();
+        for _ in 0..rng.gen_range(0..6) {
+            let start = rng.gen_range(0..64);
+            let end = rng.gen_range(start..=64);
+            members[start..end].iter_mut().for_each(|m| *m = true);
+            ranges.push(start..end);
+        }
+        (IntervalSet::from_ranges(ranges), members)
+    }
+
+    fn members(set: &IntervalSet) -> Vec<bool> {
+        (0..64)
+            .map(|id| set.ranges().iter().any(|r| r.contains(&id)))
+            .collect()
+    }
+
+    #[test]
+    fn test_random_interval_sets() {
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..1000 {
+            let (a, in_a) = random_set(&mut rng);
+            let (b, in_b) = random_set(&mut rng);
+            for set in [&a, &b] {
+                for pair in set.ranges().windows(2) {
+                    assert!(pair[0].end < pair[1].start);
+                }
+            }
+            assert_eq!(members(&a), in_a);
+            let expected = |f: fn(bool, bool) -> bool| -> Vec<bool> {
+                in_a.iter()
+                    .zip(in_b.iter())
+                    .map(|(x, y)| f(*x, *y))
+                    .collect()
+            };
+            assert_eq!(members(&a.union(&b)), expected(|x, y| x || y));
+            assert_eq!(members(&a.intersection(&b)), expected(|x, y| x && y));
+            assert_eq!(members(&a.difference(&b)), expected(|x, y| x && !y));
+        }
     }
 }